@@ -1,25 +1,99 @@
 use std::{
     cell::{Cell, RefCell},
+    collections::{BTreeMap, BTreeSet},
     convert::TryFrom as _,
     io::{Cursor, IoSlice, IoSliceMut, Read as _, Seek as _, SeekFrom, Write as _},
     path::{Path, PathBuf},
-    str::FromStr as _,
 };
 
-use blockchain_traits::PendingTransaction;
-use oasis_types::Address;
+use blockchain_traits::{
+    KVStore, KVStoreMut, PendingTransaction, Receipt as _, TransactionOutcome,
+};
+use oasis_types::{Address, ErrorCode};
 use wasi_types::{
-    ErrNo, Fd, FdFlags, FdStat, FileDelta, FileSize, FileStat, FileType, OpenFlags, Rights, Whence,
+    ClockId, ErrNo, Fd, FdFlags, FdStat, FileDelta, FileSize, FileStat, FileType, FstFlags,
+    OpenFlags, Rights, Timestamp, Whence,
 };
 
 use crate::{
+    chunked::{self, ChunkedFile},
     file::{File, FileCache, FileKind, CHAIN_DIR_FILENO, HOME_DIR_FILENO},
-    Result,
+    random::RandomStream,
+    wasm, Result,
 };
 
+/// The names of the read-only files in each account's directory. @see `BCFS::open`.
+/// Files other than `balance` and `bytecode` are dot-prefixed so that they don't shadow the
+/// files that services already store.
+const ACCOUNT_FILES: &[&str] = &[
+    "balance",
+    "bytecode",
+    ".expiry",
+    ".code_hash",
+    ".nonce",
+    ".interface",
+];
+
+/// The default maximum number of fds that may be open at once, including the pre-opened fds.
+pub const DEFAULT_MAX_FDS: u32 = 1024;
+
+/// An unchunked file is stored as a single value that is loaded into memory whole, so it can
+/// be no larger than the address space of a wasm32 service.
+const MAX_UNCHUNKED_FILE_SIZE: u64 = u32::max_value() as u64;
+
 pub struct BCFS {
     files: Vec<Option<File>>,
     home_addr: Address,
+    max_fds: u32,
+    quota: Quota,
+    chunk_size: Option<u64>,
+    random: Option<RandomStream>,
+
+    /// Whether writes are staged until `BCFS::commit`. @see `BCFS::with_staged_writes`.
+    stage_writes: Cell<bool>,
+
+    /// Writes to the storage of the home account that have not yet been written to the state
+    /// of the transaction, by key. `None` means that the key was removed. @see `BCFS::state`.
+    staged: RefCell<BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+
+    /// The storage used by the home account, which is computed when it's first needed and
+    /// then updated as the storage is modified through `BCFS::state`.
+    usage: Cell<Option<Usage>>,
+}
+
+/// Limits on the storage that can be used by the home account. `None` means no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// The maximum total size of the keys and values in the account's storage.
+    pub max_bytes: Option<u64>,
+
+    /// The maximum number of keys in the account's storage.
+    pub max_keys: Option<u64>,
+}
+
+/// The storage used by the home account, including the keys that store file metadata.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// The total size of the keys and values in the account's storage.
+    pub bytes: u64,
+
+    /// The number of keys in the account's storage.
+    pub keys: u64,
+}
+
+impl Usage {
+    /// Accounts for changing the value at `key` from one of `prev_len` bytes to one of `len`
+    /// bytes, where `None` means that there is no value.
+    fn update(&mut self, key: &[u8], prev_len: Option<usize>, len: Option<usize>) {
+        if let Some(prev_len) = prev_len {
+            self.bytes -= (key.len() + prev_len) as u64;
+            self.keys -= 1;
+        }
+        if let Some(len) = len {
+            self.bytes += (key.len() + len) as u64;
+            self.keys += 1;
+        }
+    }
 }
 
 impl BCFS {
@@ -28,11 +102,70 @@ impl BCFS {
         Self {
             files: File::defaults(blockchain_name.as_ref()),
             home_addr,
+            max_fds: DEFAULT_MAX_FDS,
+            quota: Quota::default(),
+            chunk_size: None,
+            random: None,
+            stage_writes: Cell::new(false),
+            staged: RefCell::new(BTreeMap::new()),
+            usage: Cell::new(None),
+        }
+    }
+
+    /// Stages changes to files and directories until `BCFS::commit` instead of writing them to
+    /// the state of the transaction as they're made, so that they're hidden from the calls
+    /// made by the transaction and can be discarded by `BCFS::abort`. Hosts that enable this
+    /// must call `commit` when the service exits successfully, or the changes are lost.
+    pub fn with_staged_writes(self) -> Self {
+        self.stage_writes.set(true);
+        self
+    }
+
+    /// Stores files created by this fs in chunks of `chunk_size` bytes so that reading or
+    /// writing part of a large file only loads and stores the chunks involved.
+    /// Existing files keep the layout with which they were created. @see `crate::chunked`.
+    pub fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    /// Limits the storage that can be used by the home account. Writes and flushes that
+    /// would exceed the `quota` return `ErrNo::DQuot`.
+    pub fn with_quota(mut self, quota: Quota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    /// Returns the storage currently used by the home account.
+    pub fn usage(&self, ptx: &dyn PendingTransaction) -> Usage {
+        if let Some(usage) = self.usage.get() {
+            return usage;
         }
+        let state = self.state(ptx);
+        let usage = state
+            .scan_prefix(&[])
+            .into_iter()
+            .fold(Usage::default(), |mut usage, key| {
+                usage.update(&key, None, state.get(&key).map(|value| value.len()));
+                usage
+            });
+        self.usage.set(Some(usage));
+        usage
+    }
+
+    /// Sets the maximum number of fds that may be open at once, including the pre-opened fds.
+    /// Opening a file when `max_fds` are already open returns `ErrNo::MFile`.
+    pub fn with_max_fds(mut self, max_fds: u32) -> Self {
+        self.max_fds = max_fds;
+        self
     }
 
     /// Returns pre-opened dir fds. @see `crate::file::special_file_ctor`
     pub fn prestat(&mut self, _ptx: &mut dyn PendingTransaction, fd: Fd) -> Result<&Path> {
+        if u32::from(fd) > HOME_DIR_FILENO {
+            return Err(ErrNo::BadF); // Directories opened by the service are not pre-opened.
+        }
         match &self.file(fd)?.kind {
             FileKind::Directory { path } => Ok(path),
             _ => Err(ErrNo::BadF),
@@ -46,41 +179,105 @@ impl BCFS {
     /// The blockchain environment is exposed through files in `/opt/<chain_name>/`:
     /// * `<address>/balance` - contains the read-only public balance of the account at `<address>`
     /// * `<address>/bytecode` - contains the read-only Wasm bytecode of the account at `<address>`
+    /// * `<address>/.expiry` - the time at which the account expires, in nanoseconds since the
+    ///   Unix epoch as a little-endian u64, or empty if the account does not expire
+    /// * `<address>/.code_hash` - the keccak256 hash of the account's bytecode
+    /// * `<address>/.nonce` - the number of transactions sent by the account, as a
+    ///   little-endian u64
+    /// * `<address>/.interface` - the serialized interface in the `oasis-interface` custom section
+    ///   of the account's bytecode. Does not exist if the bytecode has no such section.
     /// * `log` - an append-only file to which events can be written. @see `BCFS::parse_log`.
+    ///   Flushing a malformed event fails with `ErrNo::Inval` and discards it.
+    /// * `events` - a read-only file containing the events emitted so far in the call tree of
+    ///   the current transaction, each in the format of `log`. @see
+    ///   `PendingTransaction::events`.
     ///
     /// The user's home directory is `/opt/<chain_name>/<address>`.
+    /// Directories within the home directory can be opened using `OpenFlags::DIRECTORY`.
+    ///
+    /// The rights of the returned fd are those of `fs_rights_base` and `fs_rights_inheriting`
+    /// that are allowed by the kind of file (e.g., `balance` is read-only and `log` is
+    /// append-only) and are inheriting rights of `curdir`. Returns `ErrNo::NotCapable` if those
+    /// rights don't include the rights needed by the flags, e.g., `FD_WRITE` for
+    /// `OpenFlags::TRUNC` and `FD_SYNC` for `FdFlags::SYNC`.
+    #[allow(clippy::too_many_arguments)]
     pub fn open(
         &mut self,
         ptx: &mut dyn PendingTransaction,
         curdir: Fd,
         path: &Path,
         open_flags: OpenFlags,
+        fs_rights_base: Rights,
+        fs_rights_inheriting: Rights,
         fd_flags: FdFlags,
     ) -> Result<Fd> {
-        if open_flags.contains(OpenFlags::DIRECTORY) {
-            // The virtual filesystem does not yet allow opening directories.
-            return Err(ErrNo::NotSup);
-        }
-
         match &self.file(curdir)?.kind {
             FileKind::Directory { .. } => (),
             _ => return Err(ErrNo::BadF),
         };
 
+        let mut required_rights = Rights::PATH_OPEN;
+        if open_flags.contains(OpenFlags::CREATE) {
+            required_rights |= Rights::PATH_CREATE_FILE;
+        }
+        if open_flags.contains(OpenFlags::TRUNC) {
+            required_rights |= Rights::PATH_FILESTAT_SET_SIZE;
+        }
+        let curdir_rights_inheriting = self
+            .file_with_rights(curdir, required_rights)?
+            .rights_inheriting;
+        let rights_base = fs_rights_base & curdir_rights_inheriting;
+        let rights_inheriting = fs_rights_inheriting & curdir_rights_inheriting;
+
+        if open_flags.contains(OpenFlags::DIRECTORY) {
+            return self.open_dir(
+                ptx,
+                curdir,
+                path,
+                open_flags,
+                rights_base,
+                rights_inheriting,
+                fd_flags,
+            );
+        }
+
+        let mut flag_rights = Rights::empty();
+        if open_flags.contains(OpenFlags::TRUNC) {
+            flag_rights |= Rights::FD_WRITE;
+        }
+        if fd_flags.contains(FdFlags::DSYNC) {
+            flag_rights |= Rights::FD_DATASYNC;
+        }
+        if fd_flags.intersects(FdFlags::RSYNC | FdFlags::SYNC) {
+            flag_rights |= Rights::FD_SYNC;
+        }
+        if !rights_base.contains(flag_rights) {
+            return Err(ErrNo::NotCapable);
+        }
+
+        let fd = self.alloc_fd()?; // before the file is eagerly created
+
         let mut file_exists = true;
         let file_kind = match self.canonicalize_path(curdir, path)? {
             (None, path) if path == Path::new("log") => FileKind::Log,
+            (None, path) if path == Path::new("events") => FileKind::Events,
             (Some(addr), path) if path == Path::new("balance") => FileKind::Balance { addr },
             (Some(addr), path) if path == Path::new("bytecode") => FileKind::Bytecode { addr },
+            (Some(addr), path) if path == Path::new(".expiry") => FileKind::Expiry { addr },
+            (Some(addr), path) if path == Path::new(".code_hash") => FileKind::CodeHash { addr },
+            (Some(addr), path) if path == Path::new(".nonce") => FileKind::Nonce { addr },
+            (Some(addr), path) if path == Path::new(".interface") => FileKind::Interface { addr },
             (Some(addr), path) if addr == self.home_addr => {
                 let key = Self::key_for_path(&path)?;
-                file_exists = ptx.state().contains(&key);
-                if file_exists && open_flags.contains(OpenFlags::EXCL) {
+                file_exists = self.state(ptx).contains(&key);
+                if !file_exists && self.dir_exists(ptx, &path)? {
+                    return Err(ErrNo::IsDir);
+                } else if file_exists && open_flags.contains(OpenFlags::EXCL) {
                     return Err(ErrNo::Exist);
                 } else if !file_exists && !open_flags.contains(OpenFlags::CREATE) {
                     return Err(ErrNo::NoEnt);
                 } else if !file_exists {
-                    ptx.state_mut().set(&key, &[]);
+                    self.create_file(ptx, &key)?;
                     // ^ This must be done eagerly to match POSIX which immediately creates the file.
                 }
                 FileKind::Regular { key }
@@ -99,9 +296,25 @@ impl BCFS {
             }
         }
 
-        let fd = self.alloc_fd()?;
-        let (buf, dirty) = if !file_exists || open_flags.contains(OpenFlags::TRUNC) {
-            (FileCache::Present(Cursor::new(Vec::new())), true)
+        let chunked_file = match &file_kind {
+            FileKind::Regular { key } => ChunkedFile::load(&self.state(ptx), key),
+            _ => None,
+        };
+        let (buf, dirty) = if let Some(mut chunked_file) = chunked_file {
+            let key = match &file_kind {
+                FileKind::Regular { key } => key,
+                _ => unreachable!("only regular files are chunked"),
+            };
+            let truncate = open_flags.contains(OpenFlags::TRUNC);
+            if truncate {
+                chunked_file.set_size(&self.state(ptx), key, 0);
+            } else if fd_flags.contains(FdFlags::APPEND) {
+                chunked_file.position = chunked_file.size;
+            }
+            (FileCache::Chunked(chunked_file), truncate)
+        } else if !file_exists || open_flags.contains(OpenFlags::TRUNC) {
+            // A file that was just created is already stored empty, so it's clean.
+            (FileCache::Present(Cursor::new(Vec::new())), file_exists)
         } else {
             (
                 FileCache::Absent(if fd_flags.contains(FdFlags::APPEND) {
@@ -112,86 +325,435 @@ impl BCFS {
                 false,
             )
         };
-        self.files.push(Some(File {
-            kind: file_kind,
-            flags: fd_flags,
-            metadata: Cell::new(if file_exists {
-                None
-            } else {
-                Some(Self::default_filestat())
-            }),
-            buf: RefCell::new(buf),
-            dirty: Cell::new(dirty),
-        }));
+        self.insert_file(
+            fd,
+            File {
+                flags: fd_flags,
+                rights: file_kind.base_rights() & rights_base,
+                rights_inheriting: file_kind.inheriting_rights() & rights_inheriting,
+                kind: file_kind,
+                metadata: Cell::new(None),
+                buf: RefCell::new(buf),
+                dirty: Cell::new(dirty),
+            },
+        );
         Ok(fd)
     }
 
+    /// Reads the entries of the directory open at `fd` into `buf`, starting at the entry
+    /// with index `cookie`. Each entry is a WASI `dirent`
+    /// (`d_next: u64, d_ino: u64, d_namlen: u32, d_type: u8`, padded to 24 bytes)
+    /// followed by the entry's name. The last entry is truncated if `buf` is too small to
+    /// contain it, and the caller should continue reading from that entry's cookie.
+    /// Returns the number of bytes written into `buf`.
+    pub fn readdir(
+        &self,
+        ptx: &mut dyn PendingTransaction,
+        fd: Fd,
+        buf: &mut [u8],
+        cookie: u64,
+    ) -> Result<usize> {
+        self.file_with_rights(fd, Rights::FD_READDIR)?;
+        let dir_path = self.home_dir_path(fd)?;
+        let prefix = Self::dir_prefix(&dir_path)?;
+        let parent_inode = match dir_path.parent() {
+            Some(parent) => Self::inode(&Self::dir_prefix(parent)?),
+            None => 0, // The parent of the home directory is not in the home directory.
+        };
+        let mut entries = vec![
+            (b".".to_vec(), FileType::Directory, Self::inode(&prefix)),
+            (b"..".to_vec(), FileType::Directory, parent_inode),
+        ];
+        for (name, file_type) in self.dir_entries(ptx, &dir_path)? {
+            let mut key = prefix.clone();
+            key.extend_from_slice(&name);
+            if let FileType::Directory = file_type {
+                key.push(b'/');
+            }
+            entries.push((name, file_type, Self::inode(&key)));
+        }
+
+        let start = usize::try_from(cookie).unwrap_or(usize::max_value());
+        let mut nbytes = 0;
+        for (i, (name, file_type, inode)) in entries.into_iter().enumerate().skip(start) {
+            if nbytes == buf.len() {
+                break;
+            }
+            let mut dirent = Vec::with_capacity(DIRENT_SIZE + name.len());
+            dirent.extend_from_slice(&(i as u64 + 1).to_le_bytes()); // d_next
+            dirent.extend_from_slice(&inode.to_le_bytes()); // d_ino
+            dirent.extend_from_slice(&(name.len() as u32).to_le_bytes()); // d_namlen
+            dirent.push(file_type as u8); // d_type
+            dirent.resize(DIRENT_SIZE, 0);
+            dirent.extend_from_slice(&name);
+
+            let entry_nbytes = std::cmp::min(dirent.len(), buf.len() - nbytes);
+            buf[nbytes..(nbytes + entry_nbytes)].copy_from_slice(&dirent[..entry_nbytes]);
+            nbytes += entry_nbytes;
+        }
+        Ok(nbytes)
+    }
+
+    /// Creates a new, empty directory at `path`.
+    pub fn create_directory(
+        &mut self,
+        ptx: &mut dyn PendingTransaction,
+        curdir: Fd,
+        path: &Path,
+    ) -> Result<()> {
+        self.file_with_rights(curdir, Rights::PATH_CREATE_DIRECTORY)?;
+        let path = self.home_path(curdir, path)?;
+        if Self::is_account_file(&path)
+            || self.state(ptx).contains(&Self::key_for_path(&path)?)
+            || self.dir_exists(ptx, &path)?
+        {
+            return Err(ErrNo::Exist);
+        }
+        let marker = Self::dir_prefix(&path)?;
+        self.check_quota(ptx, &[(marker.as_slice(), 0)])?;
+        self.state(ptx).set(&marker, &[]);
+        self.write_through(ptx);
+        Ok(())
+    }
+
+    /// Removes the empty directory at `path`.
+    pub fn remove_directory(
+        &mut self,
+        ptx: &mut dyn PendingTransaction,
+        curdir: Fd,
+        path: &Path,
+    ) -> Result<()> {
+        self.file_with_rights(curdir, Rights::PATH_REMOVE_DIRECTORY)?;
+        let path = self.home_path(curdir, path)?;
+        if self.state(ptx).contains(&Self::key_for_path(&path)?) {
+            return Err(ErrNo::NotDir);
+        }
+        let prefix = Self::dir_prefix(&path)?;
+        let keys = self.state(ptx).scan_prefix(&prefix);
+        if keys.is_empty() {
+            return Err(ErrNo::NoEnt);
+        } else if keys.iter().any(|key| key.len() > prefix.len()) {
+            return Err(ErrNo::NotEmpty);
+        }
+        self.state(ptx).remove(&prefix);
+        self.write_through(ptx);
+        Ok(())
+    }
+
     pub fn tempfile(&mut self, _ptx: &mut dyn PendingTransaction) -> Result<Fd> {
         let fd = self.alloc_fd()?;
-        self.files.push(Some(File {
-            kind: FileKind::Temporary,
-            flags: FdFlags::empty(),
-            metadata: Cell::new(Some(Self::default_filestat())),
-            buf: RefCell::new(FileCache::Present(Cursor::new(Vec::new()))),
-            dirty: Cell::new(false),
-        }));
+        self.insert_file(
+            fd,
+            File {
+                kind: FileKind::Temporary,
+                flags: FdFlags::empty(),
+                rights: FileKind::Temporary.base_rights(),
+                rights_inheriting: Rights::empty(),
+                metadata: Cell::new(Some(Self::default_filestat())),
+                buf: RefCell::new(FileCache::Present(Cursor::new(Vec::new()))),
+                dirty: Cell::new(false),
+            },
+        );
         Ok(fd)
     }
 
+    /// Calls `callee` with the provided `value` and `input` using `PendingTransaction::transact`.
+    /// Returns a read-only fd containing the output of the call, or its error data if the call
+    /// was aborted, and the outcome of the call as the errno expected by the guest:
+    /// * `ErrNo::Inval` - the input was invalid
+    /// * `ErrNo::NoEnt` - the callee does not exist
+    /// * `ErrNo::DQuot` - the caller can't pay the value
+    /// * `ErrNo::ConnAborted` - the call ran but did not succeed
+    pub fn transact(
+        &mut self,
+        ptx: &mut dyn PendingTransaction,
+        callee: Address,
+        value: u128,
+        input: &[u8],
+    ) -> Result<(Fd, Result<()>)> {
+        let fd = self.alloc_fd()?; // before the call, which can't be undone
+        let receipt = ptx.transact(callee, value, input);
+        self.usage.set(None); // The callee may have called back into the home account.
+        self.insert_output(fd, receipt.output().to_vec());
+        Ok((fd, Self::outcome_errno(receipt.outcome())))
+    }
+
+    /// Creates a service with the provided `value` and `code` using `PendingTransaction::create`.
+    /// If the service was created, the returned fd contains its address. Otherwise, the fd and
+    /// the errno are those returned by `BCFS::transact`.
+    pub fn create(
+        &mut self,
+        ptx: &mut dyn PendingTransaction,
+        value: u128,
+        code: &[u8],
+    ) -> Result<(Fd, Result<()>)> {
+        let fd = self.alloc_fd()?;
+        let receipt = ptx.create(value, code);
+        self.usage.set(None); // The new service may have called back into the home account.
+        let output = match receipt.outcome() {
+            TransactionOutcome::Success => receipt.callee().as_ref().to_vec(),
+            _ => receipt.output().to_vec(),
+        };
+        self.insert_output(fd, output);
+        Ok((fd, Self::outcome_errno(receipt.outcome())))
+    }
+
+    /// Inserts a read-only file containing the output of a call.
+    fn insert_output(&mut self, fd: Fd, output: Vec<u8>) {
+        self.insert_file(
+            fd,
+            File {
+                kind: FileKind::Temporary,
+                flags: FdFlags::empty(),
+                rights: Rights::FD_READ
+                    | Rights::FD_SEEK
+                    | Rights::FD_TELL
+                    | Rights::FD_FILESTAT_GET
+                    | Rights::POLL_FD_READWRITE,
+                rights_inheriting: Rights::empty(),
+                metadata: Cell::new(None),
+                buf: RefCell::new(FileCache::Present(Cursor::new(output))),
+                dirty: Cell::new(false),
+            },
+        );
+    }
+
+    /// Returns the errno that reports `outcome` to the guest, as registered by `ErrorCode`,
+    /// through which `oasis_std::backend::wasi` maps it back to an `RpcError`.
+    fn outcome_errno(outcome: TransactionOutcome) -> Result<()> {
+        match ErrNo::from(ErrorCode::from(outcome)) {
+            ErrNo::Success => Ok(()),
+            errno => Err(errno),
+        }
+    }
+
+    /// Returns the time of `clock_id`. Services must not observe the time at which a node
+    /// happens to execute them, so every clock reads the timestamp of the current block.
+    pub fn clock_time_get(
+        &self,
+        ptx: &dyn PendingTransaction,
+        _clock_id: ClockId,
+        _precision: Timestamp,
+    ) -> Result<Timestamp> {
+        Ok(ptx.block_timestamp().into())
+    }
+
+    /// Fills `buf` with bytes from a stream that is deterministic for the current transaction.
+    /// Successive calls continue the stream. @see `crate::random`.
+    pub fn random_get(&mut self, ptx: &dyn PendingTransaction, buf: &mut [u8]) -> Result<()> {
+        self.random
+            .get_or_insert_with(|| RandomStream::new(ptx))
+            .fill(buf);
+        Ok(())
+    }
+
+    /// Writes back the unflushed changes to the file at `fd`. The changes of an fd that has
+    /// since lost `Rights::FD_WRITE` are discarded instead.
     pub fn flush(&mut self, ptx: &mut dyn PendingTransaction, fd: Fd) -> Result<()> {
         let file = self.file(fd)?;
         if !file.dirty.get() {
             return Ok(());
+        } else if !file.rights.contains(Rights::FD_WRITE) {
+            Self::discard_changes(file, &self.state(ptx));
+            return Ok(());
         }
-        let maybe_cursor = file.buf.borrow();
-        let buf = match &*maybe_cursor {
+        let mut maybe_cursor = file.buf.borrow_mut();
+        let buf = match &mut *maybe_cursor {
+            FileCache::Present(cursor) if file.kind.is_log() => {
+                // Each flush emits the event written since the previous flush.
+                let record = std::mem::replace(cursor.get_mut(), Vec::new());
+                cursor.set_position(0);
+                file.dirty.set(false);
+                return match Self::parse_log(&record) {
+                    Some((topics, data)) => {
+                        ptx.emit(&topics, data);
+                        Ok(())
+                    }
+                    None => Err(ErrNo::Inval),
+                };
+            }
             FileCache::Present(cursor) => cursor.get_ref(),
             FileCache::Absent(_) => return Ok(()),
+            FileCache::Chunked(chunked_file) => {
+                let key = match &file.kind {
+                    FileKind::Regular { key } => key,
+                    _ => unreachable!("only regular files are chunked"),
+                };
+                self.write_chunked_file(ptx, key, chunked_file)?;
+                for f in self.aliases(fd, key) {
+                    if let FileCache::Chunked(f_chunked_file) = &mut *f.buf.borrow_mut() {
+                        if let Some(mut reloaded) = ChunkedFile::load(&self.state(ptx), key) {
+                            reloaded.position = f_chunked_file.position;
+                            *f_chunked_file = reloaded;
+                        }
+                    }
+                    f.dirty.set(false);
+                    f.metadata.set(None);
+                }
+                file.dirty.set(false);
+                file.metadata.set(None);
+                return Ok(());
+            }
         };
         match &file.kind {
             FileKind::Stdin
             | FileKind::Bytecode { .. }
             | FileKind::Balance { .. }
+            | FileKind::Expiry { .. }
+            | FileKind::CodeHash { .. }
+            | FileKind::Nonce { .. }
+            | FileKind::Interface { .. }
+            | FileKind::Events
             | FileKind::Directory { .. }
             | FileKind::Temporary => (),
-            FileKind::Stdout => ptx.ret(buf),
-            FileKind::Stderr => ptx.err(buf),
-            FileKind::Log => {
-                if let Some((topics, data)) = Self::parse_log(buf) {
-                    ptx.emit(&topics, data);
-                }
+            FileKind::Stdout => {
+                ptx.ret(buf);
+                file.dirty.set(false);
+            }
+            FileKind::Stderr => {
+                ptx.err(buf);
+                file.dirty.set(false);
             }
+            FileKind::Log => unreachable!("the log was flushed above"),
             FileKind::Regular { key } => {
-                ptx.state_mut().set(&key, &buf);
-                for f in self.files[(HOME_DIR_FILENO as usize + 1)..].iter() {
-                    // find any aliased files and populate them with the flushed buffer
-                    if let Some(File {
-                        kind: FileKind::Regular { key: f_key },
-                        ..
-                    }) = f
-                    {
-                        let f = f.as_ref().unwrap();
-                        if key != f_key || f as *const File == file as *const File {
-                            continue;
-                        }
-                        let mut f_buf = f.buf.borrow_mut();
-                        let mut cursor = Cursor::new(buf.clone());
-                        cursor
-                            .seek(match &*f_buf {
-                                FileCache::Absent(seek_from) => *seek_from,
-                                FileCache::Present(cursor) => SeekFrom::Start(cursor.position()),
-                            })
-                            .ok(); // deal with the error when the file is actually read
-                        *f_buf = FileCache::Present(cursor);
-                        f.metadata.replace(None);
-                    }
+                self.write_file(ptx, &key, &buf)?;
+                // populate any aliased files with the flushed buffer
+                for f in self.aliases(fd, key) {
+                    let mut f_buf = f.buf.borrow_mut();
+                    let mut cursor = Cursor::new(buf.clone());
+                    cursor
+                        .seek(match &*f_buf {
+                            FileCache::Absent(seek_from) => *seek_from,
+                            FileCache::Present(cursor) => SeekFrom::Start(cursor.position()),
+                            FileCache::Chunked(_) => unreachable!("file is not chunked"),
+                        })
+                        .ok(); // deal with the error when the file is actually read
+                    *f_buf = FileCache::Present(cursor);
+                    f.metadata.replace(None);
                 }
                 file.dirty.set(false);
+                file.metadata.set(None);
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes every fd that has unflushed changes and writes the changes staged by flushes to
+    /// the state of `ptx`. Hosts should call this when the service exits successfully so that
+    /// no changes are lost, even if their fds were left open.
+    ///
+    /// If writes are staged, changes to files and directories are visible only through this fs
+    /// until then, and not to the calls made by the transaction, even if the calls re-enter the
+    /// home account, in which case the changes that they make to the same keys are overwritten.
+    ///
+    /// The changes are checked against the quota and logged events are validated before
+    /// anything is written, and events are emitted only once every file has been flushed.
+    /// A commit that fails writes nothing to the state of `ptx` and emits no events, but the
+    /// changes of the fds that it flushed are lost, so the host should then call `abort`.
+    /// Output is returned after the files have been written.
+    pub fn commit(&mut self, ptx: &mut dyn PendingTransaction) -> Result<()> {
+        let dirty_fds: Vec<Fd> = self
+            .files
+            .iter()
+            .enumerate()
+            .filter_map(|(fd, f)| match f {
+                Some(f) if f.dirty.get() => Some(Fd::from(fd as u32)),
+                _ => None,
+            })
+            .collect();
+
+        let mut entries = BTreeMap::new();
+        for fd in dirty_fds.iter() {
+            let file = self.file(*fd)?;
+            if !file.rights.contains(Rights::FD_WRITE) {
+                continue; // Its changes will be discarded.
+            }
+            match (&file.kind, &*file.buf.borrow()) {
+                (FileKind::Regular { key }, FileCache::Chunked(chunked_file)) => {
+                    entries.extend(chunked_file.dirty_entries(key));
+                    entries.insert(key.clone(), 0);
+                    entries.insert(Self::meta_key(key), FileTimes::SIZE);
+                }
+                (FileKind::Regular { key }, FileCache::Present(cursor)) => {
+                    entries.insert(key.clone(), cursor.get_ref().len());
+                    entries.insert(Self::meta_key(key), FileTimes::SIZE);
+                }
+                (FileKind::Log, FileCache::Present(cursor)) => {
+                    if Self::parse_log(cursor.get_ref()).is_none() {
+                        return Err(ErrNo::Inval);
+                    }
+                }
+                _ => (),
             }
         }
+        self.check_quota(ptx, &entries.into_iter().collect::<Vec<_>>())?;
+
+        let mut outputs = Vec::new();
+        let mut logs = Vec::new();
+        let mut others = Vec::new();
+        for fd in dirty_fds {
+            match self.files[fd_usize(fd)].as_ref().map(|f| &f.kind) {
+                Some(FileKind::Stdout) | Some(FileKind::Stderr) => outputs.push(fd),
+                Some(FileKind::Log) => logs.push(fd),
+                _ => others.push(fd),
+            }
+        }
+
+        let (prev_staged, prev_usage) = (self.staged.borrow().clone(), self.usage.get());
+        let stage_writes = self.stage_writes.replace(true);
+        let flushed = others.into_iter().try_for_each(|fd| self.flush(ptx, fd));
+        self.stage_writes.set(stage_writes);
+        if let Err(errno) = flushed {
+            self.staged.replace(prev_staged);
+            self.usage.set(prev_usage);
+            return Err(errno);
+        }
+
+        for fd in logs {
+            self.flush(ptx, fd)?; // The events were validated above.
+        }
+        self.write_staged(ptx);
+        for fd in outputs {
+            self.flush(ptx, fd)?;
+        }
         Ok(())
     }
 
+    /// Discards the unflushed changes to every fd and, if writes are staged, the uncommitted
+    /// changes to files and directories, including their creation. Hosts should call this when
+    /// the service exits unsuccessfully. Changes that were already written, and events that
+    /// were already logged, are discarded when the transaction is reverted.
+    pub fn abort(&mut self, ptx: &dyn PendingTransaction) {
+        self.staged.borrow_mut().clear();
+        self.usage.set(None);
+        for file in self.files.iter().filter_map(Option::as_ref) {
+            Self::discard_changes(file, ptx.state());
+        }
+    }
+
+    /// Discards the unflushed changes to `file` so that it's reloaded from `state`.
+    /// A regular file is reloaded even if it's clean because its cache may contain flushed
+    /// changes that are no longer in `state`.
+    fn discard_changes(file: &File, state: &dyn KVStore) {
+        let mut buf = file.buf.borrow_mut();
+        match (&file.kind, &*buf) {
+            (FileKind::Temporary, _) => return, // There's nowhere to reload it from.
+            (FileKind::Regular { key }, FileCache::Chunked(chunked_file)) => {
+                let mut reloaded = ChunkedFile::load(state, key)
+                    .unwrap_or_else(|| ChunkedFile::new(chunked_file.chunk_size));
+                reloaded.position = chunked_file.position;
+                *buf = FileCache::Chunked(reloaded);
+            }
+            (FileKind::Regular { .. }, FileCache::Present(cursor)) => {
+                *buf = FileCache::Absent(SeekFrom::Start(cursor.position()));
+            }
+            _ if file.dirty.get() => *buf = FileCache::Absent(SeekFrom::Start(0)),
+            _ => return,
+        }
+        file.dirty.set(false);
+        file.metadata.set(None);
+    }
+
     pub fn close(&mut self, ptx: &mut dyn PendingTransaction, fd: Fd) -> Result<()> {
         self.flush(ptx, fd)?;
         match self.files.get_mut(fd_usize(fd)) {
@@ -210,26 +772,150 @@ impl BCFS {
         curdir: Fd,
         path: &Path,
     ) -> Result<u64> {
-        let curdir_fileno = u32::from(curdir);
-        if curdir_fileno != HOME_DIR_FILENO {
+        self.file_with_rights(curdir, Rights::PATH_UNLINK_FILE)?;
+        let path = self.home_path(curdir, path)?;
+        if Self::is_account_file(&path) {
             return Err(ErrNo::Access);
         }
 
-        let (addr, path) = self.canonicalize_path(curdir, path)?;
-        match addr {
-            Some(addr) if addr == self.home_addr => (),
-            _ => return Err(ErrNo::Access),
+        let key = Self::key_for_path(&path)?;
+        if !self.state(ptx).contains(&key) && self.dir_exists(ptx, &path)? {
+            return Err(ErrNo::IsDir);
         }
+        let prev_len = match ChunkedFile::load(&self.state(ptx), &key) {
+            Some(chunked_file) => chunked_file.size,
+            None => self.state(ptx).get(&key).unwrap_or_default().len() as u64,
+        };
+        let mut state = self.state(ptx);
+        state.remove(&key);
+        state.remove(&Self::meta_key(&key));
+        chunked::remove(&mut state, &key);
+        self.write_through(ptx);
+        Ok(prev_len)
+    }
 
-        if path == Path::new("balance") || path == Path::new("bytecode") {
-            return Err(ErrNo::Access);
+    /// Atomically moves the file or directory at `path` to `new_path`, replacing the file or
+    /// empty directory that was at `new_path`, if any. Both paths must be in the home directory.
+    /// Files that are open at the old path will continue to refer to the moved file. Files that
+    /// are open at a replaced file are closed without writing back their changes.
+    pub fn rename(
+        &mut self,
+        ptx: &mut dyn PendingTransaction,
+        curdir: Fd,
+        path: &Path,
+        new_curdir: Fd,
+        new_path: &Path,
+    ) -> Result<()> {
+        self.file_with_rights(curdir, Rights::PATH_RENAME_SOURCE)?;
+        self.file_with_rights(new_curdir, Rights::PATH_RENAME_TARGET)?;
+        let path = self.home_path(curdir, path)?;
+        let new_path = self.home_path(new_curdir, new_path)?;
+        for path in [&path, &new_path].iter() {
+            if Self::is_account_file(path) {
+                return Err(ErrNo::Access);
+            }
         }
 
         let key = Self::key_for_path(&path)?;
-        let state = ptx.state_mut();
-        let prev_len = state.get(&key).unwrap_or_default().len() as u64;
-        state.remove(&key);
-        Ok(prev_len)
+        let new_key = Self::key_for_path(&new_path)?;
+        let new_is_file = self.state(ptx).contains(&new_key);
+        let mut replaced_dir = None;
+        let moved_keys: Vec<(Vec<u8>, Vec<u8>)> = if self.state(ptx).contains(&key) {
+            if !new_is_file && self.dir_exists(ptx, &new_path)? {
+                return Err(ErrNo::IsDir);
+            }
+            vec![(key, new_key)]
+        } else if self.dir_exists(ptx, &path)? {
+            let prefix = Self::dir_prefix(&path)?;
+            let new_prefix = Self::dir_prefix(&new_path)?;
+            if new_is_file {
+                return Err(ErrNo::NotDir);
+            } else if path != new_path && new_prefix.starts_with(&prefix) {
+                return Err(ErrNo::Inval); // A directory can't be moved into itself.
+            }
+            let new_dir_keys = self.state(ptx).scan_prefix(&new_prefix);
+            if new_dir_keys.iter().any(|key| key.len() > new_prefix.len()) {
+                return Err(ErrNo::NotEmpty);
+            } else if !new_dir_keys.is_empty() {
+                replaced_dir = Some(new_prefix.clone());
+            }
+            self.state(ptx)
+                .scan_prefix(&prefix)
+                .into_iter()
+                .map(|key| {
+                    let mut moved_key = new_prefix.clone();
+                    moved_key.extend_from_slice(&key[prefix.len()..]);
+                    (key, moved_key)
+                })
+                .collect()
+        } else {
+            return Err(ErrNo::NoEnt);
+        };
+
+        if path == new_path {
+            return Ok(());
+        }
+
+        if new_is_file {
+            let replaced_key = &moved_keys[0].1;
+            for file in self.files.iter_mut() {
+                match file {
+                    Some(File {
+                        kind: FileKind::Regular { key },
+                        ..
+                    }) if key == replaced_key => *file = None,
+                    _ => (),
+                }
+            }
+        }
+
+        // Write back any cached changes so that they're moved along with the file.
+        let moved_fds: Vec<Fd> = (0..self.files.len())
+            .filter(|i| match &self.files[*i] {
+                Some(File {
+                    kind: FileKind::Regular { key },
+                    ..
+                }) => moved_keys.iter().any(|(moved_key, _)| key == moved_key),
+                _ => false,
+            })
+            .map(|i| Fd::from(i as u32))
+            .collect();
+        for fd in moved_fds.iter() {
+            self.flush(ptx, *fd)?;
+        }
+
+        if let Some(replaced_dir_marker) = replaced_dir {
+            self.state(ptx).remove(&replaced_dir_marker);
+        }
+        let mut state = self.state(ptx);
+        for (key, new_key) in moved_keys.iter() {
+            let value = state.get(key).unwrap_or_default();
+            state.remove(key);
+            state.set(new_key, &value);
+
+            let (meta_key, new_meta_key) = (Self::meta_key(key), Self::meta_key(new_key));
+            match state.get(&meta_key) {
+                Some(meta) => {
+                    state.remove(&meta_key);
+                    state.set(&new_meta_key, &meta);
+                }
+                None => state.remove(&new_meta_key),
+            }
+            chunked::rename(&mut state, key, new_key);
+        }
+        self.write_through(ptx);
+
+        for fd in moved_fds {
+            if let Some(File {
+                kind: FileKind::Regular { key },
+                ..
+            }) = &mut self.files[fd_usize(fd)]
+            {
+                let (_, new_key) = moved_keys.iter().find(|(k, _)| k == key).unwrap();
+                *key = new_key.clone();
+            }
+        }
+        Ok(())
     }
 
     pub fn seek(
@@ -239,17 +925,34 @@ impl BCFS {
         offset: FileDelta,
         whence: Whence,
     ) -> Result<FileSize> {
-        let file = self.file_mut(fd)?;
+        let file = self.file_with_rights(
+            fd,
+            if offset == 0 && whence == Whence::Current {
+                Rights::FD_TELL
+            } else {
+                Rights::FD_SEEK
+            },
+        )?;
 
         let mut buf = file.buf.borrow_mut();
 
+        if let FileCache::Chunked(chunked_file) = &mut *buf {
+            let base = match whence {
+                Whence::Start => 0,
+                Whence::Current => chunked_file.position,
+                Whence::End => chunked_file.size,
+            };
+            chunked_file.position = Self::checked_offset(base, offset)?;
+            return Ok(chunked_file.position);
+        }
+
         if Whence::End == whence
             || match &*buf {
                 FileCache::Absent(SeekFrom::End(_)) => true,
                 _ => false,
             }
         {
-            Self::populate_file(ptx, &file, &mut *buf)?;
+            self.populate_file(ptx, &file, &mut *buf)?;
         }
 
         match &mut *buf {
@@ -271,6 +974,7 @@ impl BCFS {
                     _ => unreachable!("handled above"),
                 },
             },
+            FileCache::Chunked(_) => unreachable!("handled above"),
         }
     }
 
@@ -282,24 +986,147 @@ impl BCFS {
                 _ => FileType::RegularFile,
             },
             flags: file.flags,
-            rights_base: Rights::all(),
-            rights_inheriting: Rights::all(),
+            rights_base: file.rights,
+            rights_inheriting: file.rights_inheriting,
         })
     }
 
+    /// Sets the rights of `fd`. Rights can only be dropped, so requesting rights that `fd`
+    /// does not already have returns `ErrNo::NotCapable`.
+    pub fn set_rights(
+        &mut self,
+        _ptx: &mut dyn PendingTransaction,
+        fd: Fd,
+        rights: Rights,
+        rights_inheriting: Rights,
+    ) -> Result<()> {
+        let file = self.file_mut(fd)?;
+        if !file.rights.contains(rights) || !file.rights_inheriting.contains(rights_inheriting) {
+            return Err(ErrNo::NotCapable);
+        }
+        file.rights = rights;
+        file.rights_inheriting = rights_inheriting;
+        Ok(())
+    }
+
     pub fn filestat(&self, ptx: &dyn PendingTransaction, fd: Fd) -> Result<FileStat> {
-        let file = self.file(fd)?;
-        Self::populate_file(ptx, file, &mut *file.buf.borrow_mut())
+        let file = self.file_with_rights(fd, Rights::FD_FILESTAT_GET)?;
+        if let FileKind::Directory { .. } = file.kind {
+            let inode = self
+                .home_dir_path(fd)
+                .and_then(|path| Self::dir_prefix(&path))
+                .map(|prefix| Self::inode(&prefix))
+                .unwrap_or_default();
+            return Ok(FileStat {
+                inode: inode.into(),
+                file_type: FileType::Directory,
+                ..Self::default_filestat()
+            });
+        }
+        self.populate_file(ptx, file, &mut *file.buf.borrow_mut())
+    }
+
+    /// Truncates the file at `fd` to `size` or extends it with zeros. The offset of the file
+    /// is left unchanged. Returns `ErrNo::DQuot` if the resized file would exceed the quota.
+    pub fn set_size(
+        &mut self,
+        ptx: &mut dyn PendingTransaction,
+        fd: Fd,
+        size: FileSize,
+    ) -> Result<()> {
+        let file = self.file_with_rights(fd, Rights::FD_FILESTAT_SET_SIZE | Rights::FD_WRITE)?;
+        let size = usize::try_from(size).map_err(|_| ErrNo::Inval)?;
+
+        let mut buf = file.buf.borrow_mut();
+        let meta = self.populate_file(ptx, &file, &mut *buf)?;
+        if let FileKind::Regular { key } = &file.kind {
+            // Absent chunks read as zeros, so extending a chunked file stores nothing new.
+            let mut entries = match &*buf {
+                FileCache::Chunked(chunked_file) => chunked_file.dirty_entries(key),
+                _ if size as u64 > MAX_UNCHUNKED_FILE_SIZE => return Err(ErrNo::FBig),
+                _ => vec![(key.to_vec(), size)],
+            };
+            entries.push((Self::meta_key(key), FileTimes::SIZE));
+            self.check_quota(ptx, &entries)?;
+        }
+        match (&mut *buf, &file.kind) {
+            (FileCache::Present(cursor), _) => cursor.get_mut().resize(size, 0),
+            (FileCache::Chunked(chunked_file), FileKind::Regular { key }) => {
+                chunked_file.set_size(&self.state(ptx), key, size as u64)
+            }
+            _ => unreachable!("file was just populated"),
+        }
+        file.dirty.set(true);
+        file.metadata.set(Some(FileStat {
+            file_size: size as u64,
+            ..meta
+        }));
+        Ok(())
+    }
+
+    /// Sets the access and/or modification times of the file at `path`, as selected by
+    /// `fst_flags`. `FstFlags::ATIM_NOW` and `FstFlags::MTIM_NOW` use the block timestamp.
+    pub fn set_times(
+        &mut self,
+        ptx: &mut dyn PendingTransaction,
+        curdir: Fd,
+        path: &Path,
+        atime: Timestamp,
+        mtime: Timestamp,
+        fst_flags: FstFlags,
+    ) -> Result<()> {
+        if fst_flags.contains(FstFlags::ATIM | FstFlags::ATIM_NOW)
+            || fst_flags.contains(FstFlags::MTIM | FstFlags::MTIM_NOW)
+        {
+            return Err(ErrNo::Inval);
+        }
+        self.file_with_rights(curdir, Rights::PATH_FILESTAT_SET_TIMES)?;
+
+        let path = self.home_path(curdir, path)?;
+        let key = Self::key_for_path(&path)?;
+        if !self.state(ptx).contains(&key) {
+            return if self.dir_exists(ptx, &path)? {
+                Ok(()) // Directories don't have times.
+            } else {
+                Err(ErrNo::NoEnt)
+            };
+        }
+
+        let now = ptx.block_timestamp();
+        let mut times = self.file_times(ptx, &key);
+        if fst_flags.contains(FstFlags::ATIM) {
+            times.atime = u64::from(atime);
+        } else if fst_flags.contains(FstFlags::ATIM_NOW) {
+            times.atime = now;
+        }
+        if fst_flags.contains(FstFlags::MTIM) {
+            times.mtime = u64::from(mtime);
+        } else if fst_flags.contains(FstFlags::MTIM_NOW) {
+            times.mtime = now;
+        }
+        self.state(ptx)
+            .set(&Self::meta_key(&key), &times.to_bytes());
+        self.write_through(ptx);
+
+        for f in self.files.iter().filter_map(Option::as_ref) {
+            if let FileKind::Regular { key: f_key } = &f.kind {
+                if *f_key == key {
+                    f.metadata.set(None);
+                }
+            }
+        }
+        Ok(())
     }
 
     pub fn tell(&self, ptx: &mut dyn PendingTransaction, fd: Fd) -> Result<FileSize> {
-        let file = self.file(fd)?;
+        let file = self.file_with_rights(fd, Rights::FD_TELL)?;
         let mut buf = file.buf.borrow_mut();
         if let FileCache::Absent(SeekFrom::End(_)) = &*buf {
-            Self::populate_file(ptx, &file, &mut *buf)?;
+            self.populate_file(ptx, &file, &mut *buf)?;
         }
         Ok(match &mut *buf {
             FileCache::Present(cursor) => cursor.position(),
+            FileCache::Chunked(chunked_file) => chunked_file.position,
             FileCache::Absent(ref mut seekfrom) => match seekfrom {
                 SeekFrom::Start(offset) => *offset,
                 SeekFrom::End(_) => unreachable!("checked above"),
@@ -362,6 +1189,55 @@ impl BCFS {
     }
 }
 
+/// The size of a WASI `dirent`, excluding the name that follows it.
+const DIRENT_SIZE: usize = 24;
+
+/// The prefix of the keys under which BCFS stores data other than files and directories,
+/// such as file metadata and chunks. File keys are paths, which can't contain NUL, so reserved
+/// keys never collide with file keys.
+const RESERVED_KEY_PREFIX: &[u8] = b"\0";
+
+/// The prefix of the keys under which file metadata is stored.
+const META_KEY_PREFIX: &[u8] = b"\0meta/";
+
+/// The times of a regular file, in nanoseconds since the Unix epoch.
+/// They're stored under the file's `meta_key` as `atime mtime ctime` (little-endian u64s).
+/// `mtime` and `ctime` are the timestamps of the blocks in which the file was last written
+/// and created, respectively.
+#[derive(Clone, Copy, Default)]
+struct FileTimes {
+    atime: u64,
+    mtime: u64,
+    ctime: u64,
+}
+
+impl FileTimes {
+    const SIZE: usize = 24;
+
+    fn to_bytes(self) -> Vec<u8> {
+        [self.atime, self.mtime, self.ctime]
+            .iter()
+            .flat_map(|time| time.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SIZE {
+            return None;
+        }
+        let time = |i: usize| {
+            let mut time_bytes = [0u8; 8];
+            time_bytes.copy_from_slice(&bytes[(i * 8)..((i + 1) * 8)]);
+            u64::from_le_bytes(time_bytes)
+        };
+        Some(Self {
+            atime: time(0),
+            mtime: time(1),
+            ctime: time(2),
+        })
+    }
+}
+
 fn fd_usize(fd: Fd) -> usize {
     usize::try_from(u32::from(fd)).unwrap() // can't fail because usize is at least 32 bits
 }
@@ -380,6 +1256,14 @@ fn seekfrom_from_offset_whence(offset: FileDelta, whence: Whence) -> Result<Seek
 
 impl BCFS {
     fn canonicalize_path(&self, curdir: Fd, path: &Path) -> Result<(Option<Address>, PathBuf)> {
+        match self.resolve_path(curdir, path)? {
+            (_, path) if path.as_os_str().is_empty() => Err(ErrNo::Inval),
+            resolved => Ok(resolved),
+        }
+    }
+
+    /// Like `canonicalize_path`, but allows `path` to resolve to the directory itself.
+    fn resolve_path(&self, curdir: Fd, path: &Path) -> Result<(Option<Address>, PathBuf)> {
         use std::path::Component;
 
         if path.has_root() {
@@ -388,7 +1272,11 @@ impl BCFS {
 
         let curdir_fileno = u32::from(curdir);
 
-        let mut canon_path = PathBuf::new();
+        let mut canon_path = if curdir_fileno == CHAIN_DIR_FILENO {
+            PathBuf::new()
+        } else {
+            self.home_dir_path(curdir).unwrap_or_default()
+        };
 
         let mut comps = path
             .components()
@@ -398,13 +1286,11 @@ impl BCFS {
         let addr = if curdir_fileno == CHAIN_DIR_FILENO {
             match comps.peek() {
                 Some(Component::Normal(maybe_addr)) => {
-                    match maybe_addr.to_str().map(Address::from_str) {
-                        Some(Ok(addr)) => {
-                            comps.next();
-                            Some(addr)
-                        }
-                        _ => None,
+                    let addr = Address::from_path_repr(Path::new(maybe_addr));
+                    if addr.is_some() {
+                        comps.next();
                     }
+                    addr
                 }
                 Some(Component::Prefix(_)) | Some(Component::RootDir) => return Err(ErrNo::NoEnt),
                 _ => None,
@@ -413,7 +1299,6 @@ impl BCFS {
             Some(self.home_addr)
         };
 
-        let mut has_path = false;
         for comp in comps {
             match comp {
                 Component::Prefix(_) | Component::RootDir => return Err(ErrNo::NoEnt),
@@ -423,18 +1308,134 @@ impl BCFS {
                         return Err(ErrNo::NoEnt);
                     }
                 }
-                Component::Normal(c) => {
-                    has_path |= !c.is_empty();
-                    canon_path.push(c);
-                }
+                Component::Normal(c) => canon_path.push(c),
             }
         }
 
-        if has_path {
-            Ok((addr, canon_path))
-        } else {
-            Err(ErrNo::Inval)
+        Ok((addr, canon_path))
+    }
+
+    /// Resolves `path` to a path in the home directory or returns `ErrNo::Access`.
+    fn home_path(&self, curdir: Fd, path: &Path) -> Result<PathBuf> {
+        match self.canonicalize_path(curdir, path)? {
+            (Some(addr), path) if addr == self.home_addr => Ok(path),
+            _ => Err(ErrNo::Access),
+        }
+    }
+
+    /// Returns the path, relative to the home directory, of the directory open at `fd`.
+    fn home_dir_path(&self, fd: Fd) -> Result<PathBuf> {
+        match &self.file(fd)?.kind {
+            FileKind::Directory { path } if path.has_root() => Err(ErrNo::NotSup),
+            FileKind::Directory { path } => Ok(path
+                .components()
+                .filter(|comp| *comp != std::path::Component::CurDir)
+                .collect()),
+            _ => Err(ErrNo::NotDir),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn open_dir(
+        &mut self,
+        ptx: &mut dyn PendingTransaction,
+        curdir: Fd,
+        path: &Path,
+        open_flags: OpenFlags,
+        rights_base: Rights,
+        rights_inheriting: Rights,
+        fd_flags: FdFlags,
+    ) -> Result<Fd> {
+        if open_flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC) {
+            return Err(ErrNo::Inval);
+        }
+        let path = match self.resolve_path(curdir, path)? {
+            (Some(addr), path) if addr == self.home_addr => path,
+            (Some(_), _) => return Err(ErrNo::NotSup), // Other accounts can't be listed.
+            (None, path) if path == Path::new("log") || path == Path::new("events") => {
+                return Err(ErrNo::NotDir)
+            }
+            (None, _) => return Err(ErrNo::NoEnt),
+        };
+        if Self::is_account_file(&path)
+            || (!path.as_os_str().is_empty()
+                && self.state(ptx).contains(&Self::key_for_path(&path)?))
+        {
+            return Err(ErrNo::NotDir);
+        } else if !self.dir_exists(ptx, &path)? {
+            return Err(ErrNo::NoEnt);
+        } else if open_flags.contains(OpenFlags::EXCL) {
+            return Err(ErrNo::Exist);
+        }
+
+        let kind = FileKind::Directory {
+            path: if path.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                path
+            },
+        };
+        let fd = self.alloc_fd()?;
+        self.insert_file(
+            fd,
+            File {
+                flags: fd_flags,
+                rights: kind.base_rights() & rights_base,
+                rights_inheriting: kind.inheriting_rights() & rights_inheriting,
+                kind,
+                metadata: Cell::new(None),
+                buf: RefCell::new(FileCache::Absent(SeekFrom::Start(0))),
+                dirty: Cell::new(false),
+            },
+        );
+        Ok(fd)
+    }
+
+    /// Returns the prefix shared by the keys of the entries in the directory at `path`.
+    /// The key of the directory's prefix itself marks the existence of an empty directory.
+    fn dir_prefix(path: &Path) -> Result<Vec<u8>> {
+        if path.as_os_str().is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut prefix = Self::key_for_path(path)?;
+        prefix.push(b'/');
+        Ok(prefix)
+    }
+
+    fn dir_exists(&self, ptx: &dyn PendingTransaction, path: &Path) -> Result<bool> {
+        Ok(path.as_os_str().is_empty()
+            || !self
+                .state(ptx)
+                .scan_prefix(&Self::dir_prefix(path)?)
+                .is_empty())
+    }
+
+    /// Returns the names and types of the entries in the directory at `path`, sorted by name.
+    fn dir_entries(
+        &self,
+        ptx: &dyn PendingTransaction,
+        path: &Path,
+    ) -> Result<impl Iterator<Item = (Vec<u8>, FileType)>> {
+        let prefix = Self::dir_prefix(path)?;
+        let mut entries = BTreeMap::new();
+        for key in self.state(ptx).scan_prefix(&prefix) {
+            if key.starts_with(RESERVED_KEY_PREFIX) {
+                continue;
+            }
+            let rel_key = &key[prefix.len()..];
+            if rel_key.is_empty() {
+                continue; // the marker of this directory
+            }
+            match rel_key.iter().position(|b| *b == b'/') {
+                Some(sep_pos) => {
+                    entries.insert(rel_key[..sep_pos].to_vec(), FileType::Directory);
+                }
+                None => {
+                    entries.insert(rel_key.to_vec(), FileType::RegularFile);
+                }
+            }
         }
+        Ok(entries.into_iter())
     }
 
     fn has_fd(&self, fd: Fd) -> bool {
@@ -451,6 +1452,31 @@ impl BCFS {
         }
     }
 
+    /// Returns the files other than the one open at `fd` that are open to the file at `key`.
+    fn aliases<'a>(&'a self, fd: Fd, key: &'a [u8]) -> impl Iterator<Item = &'a File> + 'a {
+        self.files
+            .iter()
+            .enumerate()
+            .filter(move |(i, _)| *i != fd_usize(fd))
+            .filter_map(move |(_, f)| match f {
+                Some(f) => match &f.kind {
+                    FileKind::Regular { key: f_key } if f_key.as_slice() == key => Some(f),
+                    _ => None,
+                },
+                None => None,
+            })
+    }
+
+    /// Returns the file open at `fd` if `fd` has all of the `rights`.
+    fn file_with_rights(&self, fd: Fd, rights: Rights) -> Result<&File> {
+        let file = self.file(fd)?;
+        if file.rights.contains(rights) {
+            Ok(file)
+        } else {
+            Err(ErrNo::NotCapable)
+        }
+    }
+
     fn file_mut(&mut self, fd: Fd) -> Result<&mut File> {
         match self
             .files
@@ -461,11 +1487,27 @@ impl BCFS {
         }
     }
 
+    /// Returns the lowest fd that is not open, as in POSIX.
     fn alloc_fd(&self) -> Result<Fd> {
-        if self.files.len() >= u32::max_value() as usize {
-            return Err(ErrNo::NFile); // TODO(#82)
+        let num_open = self.files.iter().filter(|f| f.is_some()).count();
+        if num_open >= self.max_fds as usize {
+            return Err(ErrNo::MFile);
+        }
+        let fd = self
+            .files
+            .iter()
+            .position(Option::is_none)
+            .unwrap_or_else(|| self.files.len());
+        u32::try_from(fd).map(Fd::from).map_err(|_| ErrNo::NFile)
+    }
+
+    fn insert_file(&mut self, fd: Fd, file: File) {
+        let fd = fd_usize(fd);
+        if fd == self.files.len() {
+            self.files.push(Some(file));
+        } else {
+            self.files[fd] = Some(file);
         }
-        Ok((self.files.len() as u32).into())
     }
 
     fn checked_offset(base: u64, offset: i64) -> Result<u64> {
@@ -478,18 +1520,167 @@ impl BCFS {
     }
 
     fn key_for_path(path: &Path) -> Result<Vec<u8>> {
-        path.to_str()
-            .ok_or(ErrNo::Inval)
-            .map(|s| s.as_bytes().to_vec())
+        match path.to_str() {
+            Some(path) if !path.contains('\0') => Ok(path.as_bytes().to_vec()),
+            _ => Err(ErrNo::Inval), // NUL is reserved for `RESERVED_KEY_PREFIX`.
+        }
+    }
+
+    /// Returns the key under which the `FileTimes` of the file at `key` are stored.
+    fn meta_key(key: &[u8]) -> Vec<u8> {
+        let mut meta_key = META_KEY_PREFIX.to_vec();
+        meta_key.extend_from_slice(key);
+        meta_key
+    }
+
+    fn file_times(&self, ptx: &dyn PendingTransaction, key: &[u8]) -> FileTimes {
+        self.state(ptx)
+            .get(&Self::meta_key(key))
+            .and_then(|bytes| FileTimes::from_bytes(&bytes))
+            .unwrap_or_default()
+    }
+
+    /// Writes `value` to the file at `key` and updates the file's times.
+    fn write_file(&self, ptx: &mut dyn PendingTransaction, key: &[u8], value: &[u8]) -> Result<()> {
+        let meta_key = Self::meta_key(key);
+        self.check_quota(
+            ptx,
+            &[(key, value.len()), (meta_key.as_slice(), FileTimes::SIZE)],
+        )?;
+        self.touch_file(ptx, key);
+        self.state(ptx).set(key, value);
+        self.write_through(ptx);
+        Ok(())
+    }
+
+    /// Writes the changes to the chunked file at `key` and updates the file's times.
+    fn write_chunked_file(
+        &self,
+        ptx: &mut dyn PendingTransaction,
+        key: &[u8],
+        chunked_file: &mut ChunkedFile,
+    ) -> Result<()> {
+        let mut entries = chunked_file.dirty_entries(key);
+        entries.push((key.to_vec(), 0));
+        entries.push((Self::meta_key(key), FileTimes::SIZE));
+        self.check_quota(ptx, &entries)?;
+        self.touch_file(ptx, key);
+        let mut state = self.state(ptx);
+        state.set(key, &[]);
+        chunked_file.flush(&mut state, key);
+        self.write_through(ptx);
+        Ok(())
+    }
+
+    /// Creates an empty file at `key`, which is chunked if a chunk size has been set.
+    fn create_file(&self, ptx: &mut dyn PendingTransaction, key: &[u8]) -> Result<()> {
+        match self.chunk_size {
+            Some(chunk_size) => {
+                self.write_chunked_file(ptx, key, &mut ChunkedFile::new(chunk_size))
+            }
+            None => self.write_file(ptx, key, &[]),
+        }
+    }
+
+    /// Updates the times of the file at `key` for a write in the current block.
+    /// Must be called before the file is created so that its creation time is recorded.
+    fn touch_file(&self, ptx: &mut dyn PendingTransaction, key: &[u8]) {
+        let now = ptx.block_timestamp();
+        let ctime = if self.state(ptx).contains(key) {
+            self.file_times(ptx, key).ctime
+        } else {
+            now
+        };
+        let times = FileTimes {
+            atime: now,
+            mtime: now,
+            ctime,
+        };
+        self.state(ptx).set(&Self::meta_key(key), &times.to_bytes());
+    }
+
+    /// Returns `ErrNo::DQuot` if setting each key in `entries` to a value of the paired length
+    /// would exceed the quota.
+    fn check_quota<K: AsRef<[u8]>>(
+        &self,
+        ptx: &dyn PendingTransaction,
+        entries: &[(K, usize)],
+    ) -> Result<()> {
+        if self.quota == Quota::default() {
+            return Ok(());
+        }
+        let state = self.state(ptx);
+        let mut usage = self.usage(ptx);
+        for (key, value_len) in entries {
+            let key = key.as_ref();
+            usage.update(
+                key,
+                state.get(key).map(|prev_value| prev_value.len()),
+                Some(*value_len),
+            );
+        }
+        let exceeds =
+            |limit: Option<u64>, used: u64| limit.map(|limit| used > limit).unwrap_or(false);
+        if exceeds(self.quota.max_bytes, usage.bytes) || exceeds(self.quota.max_keys, usage.keys) {
+            return Err(ErrNo::DQuot);
+        }
+        Ok(())
+    }
+
+    /// Writes the staged changes to the state of `ptx`.
+    fn write_staged(&self, ptx: &mut dyn PendingTransaction) {
+        let staged = std::mem::take(&mut *self.staged.borrow_mut());
+        let state = ptx.state_mut();
+        for (key, value) in staged {
+            match value {
+                Some(value) => state.set(&key, &value),
+                None => state.remove(&key),
+            }
+        }
+    }
+
+    /// Writes the changes made through `BCFS::state` to the state of `ptx`, unless writes are
+    /// staged until `commit`. Must be called by every operation that modifies the state.
+    fn write_through(&self, ptx: &mut dyn PendingTransaction) {
+        if !self.stage_writes.get() {
+            self.write_staged(ptx);
+        }
+    }
+
+    /// Returns the storage of the home account, including the writes that have not yet been
+    /// written to the state of `ptx`.
+    /// Modifying it stages the writes and keeps `BCFS::usage` up to date.
+    fn state<'a>(&'a self, ptx: &'a dyn PendingTransaction) -> StagedState<'a> {
+        StagedState {
+            state: ptx.state(),
+            staged: &self.staged,
+            usage: &self.usage,
+        }
+    }
+
+    /// Returns whether `path`, relative to an account's directory, names one of the read-only
+    /// files that describe every account. Such paths can't be used by the account's own files.
+    fn is_account_file(path: &Path) -> bool {
+        ACCOUNT_FILES.iter().any(|name| path == Path::new(name))
+    }
+
+    /// Returns the inode number of the file or directory stored at `key`, which is the
+    /// 64-bit FNV-1a hash of the key. Inode numbers are thus stable across transactions.
+    fn inode(key: &[u8]) -> u64 {
+        key.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+            (hash ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+        })
     }
 
     fn populate_file(
+        &self,
         ptx: &dyn PendingTransaction,
         file: &File,
         cache: &mut FileCache,
     ) -> Result<FileStat> {
         let file_size = match cache {
             FileCache::Present(cursor) => cursor.get_ref().len(),
+            FileCache::Chunked(chunked_file) => chunked_file.size as usize,
             FileCache::Absent(offset) => {
                 let bytes = match &file.kind {
                     FileKind::Stdin => ptx.input().to_vec(),
@@ -501,10 +1692,38 @@ impl BCFS {
                         Some(meta) => meta.balance.to_le_bytes().to_vec(),
                         None => return Err(ErrNo::NoEnt),
                     },
-                    FileKind::Regular { key } => match ptx.state().get(&key) {
+                    FileKind::Expiry { addr } => match ptx.account_meta_at(addr) {
+                        Some(meta) => match meta.expiry {
+                            Some(expiry) => u64::try_from(expiry.as_nanos())
+                                .unwrap_or(u64::max_value())
+                                .to_le_bytes()
+                                .to_vec(),
+                            None => Vec::new(),
+                        },
+                        None => return Err(ErrNo::NoEnt),
+                    },
+                    FileKind::CodeHash { addr } => match ptx.code_at(addr) {
+                        Some(code) => tiny_keccak::keccak256(code).to_vec(),
+                        None => return Err(ErrNo::NoEnt),
+                    },
+                    FileKind::Nonce { addr } => match ptx.account_meta_at(addr) {
+                        Some(meta) => meta.nonce.to_le_bytes().to_vec(),
+                        None => return Err(ErrNo::NoEnt),
+                    },
+                    FileKind::Interface { addr } => {
+                        match ptx
+                            .code_at(addr)
+                            .and_then(|code| wasm::custom_section(code, "oasis-interface"))
+                        {
+                            Some(iface) => iface.to_vec(),
+                            None => return Err(ErrNo::NoEnt),
+                        }
+                    }
+                    FileKind::Regular { key } => match self.state(ptx).get(&key) {
                         Some(val) => val.to_vec(),
                         None => return Err(ErrNo::NoEnt),
                     },
+                    FileKind::Events => Self::encode_events(ptx),
                     FileKind::Stdout | FileKind::Stderr | FileKind::Log => Vec::new(),
                     FileKind::Directory { .. } | FileKind::Temporary => return Err(ErrNo::Fault),
                 };
@@ -518,15 +1737,19 @@ impl BCFS {
         match file.metadata.get() {
             Some(meta) => Ok(meta),
             None => {
+                let (inode, times) = match &file.kind {
+                    FileKind::Regular { key } => (Self::inode(key), self.file_times(ptx, key)),
+                    _ => (0, FileTimes::default()),
+                };
                 let meta = FileStat {
                     device: 0u64.into(),
-                    inode: 0u64.into(), // TODO(#80)
+                    inode: inode.into(),
                     file_type: FileType::RegularFile,
                     num_links: 0,
                     file_size,
-                    atime: 0u64.into(), // TODO(#81)
-                    mtime: 0u64.into(),
-                    ctime: 0u64.into(),
+                    atime: times.atime.into(),
+                    mtime: times.mtime.into(),
+                    ctime: times.ctime.into(),
                 };
                 file.metadata.set(Some(meta));
                 Ok(meta)
@@ -541,20 +1764,42 @@ impl BCFS {
         bufs: &mut [IoSliceMut],
         offset: Option<SeekFrom>,
     ) -> Result<usize> {
-        let file = self.file(fd)?;
-        match file.kind {
-            FileKind::Stdout | FileKind::Stderr { .. } | FileKind::Log { .. } => {
-                return Err(ErrNo::Inval)
-            }
-            _ => (),
-        };
+        let file = self.file_with_rights(
+            fd,
+            match offset {
+                Some(_) => Rights::FD_READ | Rights::FD_SEEK,
+                None => Rights::FD_READ,
+            },
+        )?;
 
         let mut buf = file.buf.borrow_mut();
-        Self::populate_file(ptx, &file, &mut *buf)?;
+        self.populate_file(ptx, &file, &mut *buf)?;
+
+        if let (FileCache::Chunked(chunked_file), FileKind::Regular { key }) =
+            (&mut *buf, &file.kind)
+        {
+            let mut pos = match offset {
+                Some(SeekFrom::Start(offset)) => offset,
+                _ => chunked_file.position,
+            };
+            let mut nbytes = 0;
+            for slice in bufs.iter_mut() {
+                let slice_nbytes = chunked_file.read(&self.state(ptx), key, slice, pos);
+                pos += slice_nbytes as u64;
+                nbytes += slice_nbytes;
+                if slice_nbytes < slice.len() {
+                    break;
+                }
+            }
+            if offset.is_none() {
+                chunked_file.position = pos;
+            }
+            return Ok(nbytes);
+        }
 
         let cursor = match &mut *buf {
             FileCache::Present(ref mut cursor) => cursor,
-            FileCache::Absent(_) => unreachable!("file was just populated"),
+            _ => unreachable!("file was just populated"),
         };
 
         match offset {
@@ -576,22 +1821,65 @@ impl BCFS {
         bufs: &[IoSlice],
         offset: Option<SeekFrom>,
     ) -> Result<usize> {
-        let file = self.file(fd)?;
-        match file.kind {
-            FileKind::Stdin | FileKind::Bytecode { .. } | FileKind::Balance { .. } => {
-                return Err(ErrNo::Inval)
-            }
-            _ => (),
-        };
+        let file = self.file_with_rights(
+            fd,
+            match offset {
+                Some(_) => Rights::FD_WRITE | Rights::FD_SEEK,
+                None => Rights::FD_WRITE,
+            },
+        )?;
 
         let mut buf = file.buf.borrow_mut();
-        Self::populate_file(ptx, &file, &mut *buf)?;
+        self.populate_file(ptx, &file, &mut *buf)?;
+
+        if let (FileCache::Chunked(chunked_file), FileKind::Regular { key }) =
+            (&mut *buf, &file.kind)
+        {
+            let mut pos = match offset {
+                Some(SeekFrom::Start(offset)) => offset,
+                _ => chunked_file.position,
+            };
+            let write_len: usize = bufs.iter().map(|buf| buf.len()).sum();
+            let mut entries = chunked_file.write_entries(key, pos, write_len as u64)?;
+            entries.push((Self::meta_key(key), FileTimes::SIZE));
+            self.check_quota(ptx, &entries)?;
+
+            for slice in bufs.iter() {
+                pos += chunked_file.write(&self.state(ptx), key, slice, pos)? as u64;
+            }
+            if offset.is_none() {
+                chunked_file.position = pos;
+            }
+            if write_len > 0 {
+                file.dirty.replace(true);
+            }
+            return Ok(write_len);
+        }
 
         let cursor = match &mut *buf {
             FileCache::Present(ref mut cursor) => cursor,
-            FileCache::Absent(_) => unreachable!("file was just populated"),
+            _ => unreachable!("file was just populated"),
         };
 
+        if let FileKind::Regular { key } = &file.kind {
+            let write_pos = match offset {
+                Some(SeekFrom::Start(offset)) => offset,
+                _ => cursor.position(),
+            };
+            let write_len: usize = bufs.iter().map(|buf| buf.len()).sum();
+            let new_len = std::cmp::max(
+                cursor.get_ref().len(),
+                usize::try_from(write_pos).map_err(|_| ErrNo::FBig)? + write_len,
+            );
+            self.check_quota(
+                ptx,
+                &[
+                    (key.as_slice(), new_len),
+                    (Self::meta_key(key).as_slice(), FileTimes::SIZE),
+                ],
+            )?;
+        }
+
         let nbytes = match offset {
             Some(offset) => {
                 let orig_pos = cursor.position();
@@ -612,6 +1900,7 @@ impl BCFS {
     /// Format:
     /// num_topics [topic_len [topic_data; topic_len]; num_topics] data_len [data; data_len]
     /// num_* are little-endian 32-bit integers.
+    /// Returns `None` if the buffer is not exactly one log record.
     fn parse_log(buf: &[u8]) -> Option<(Vec<&[u8]>, &[u8])> {
         use nom::{complete, do_parse, length_count, length_data, named, number::complete::le_u32};
         named! {
@@ -622,7 +1911,26 @@ impl BCFS {
                 (topics, data)
             ))
         };
-        parser(buf).map(|result| result.1).ok()
+        match parser(buf) {
+            Ok((rest, log)) if rest.is_empty() => Some(log),
+            _ => None,
+        }
+    }
+
+    /// Returns the events emitted so far in the call tree of the current transaction,
+    /// concatenated in the format parsed by `parse_log`.
+    fn encode_events(ptx: &dyn PendingTransaction) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for event in ptx.events() {
+            buf.extend_from_slice(&(event.topics.len() as u32).to_le_bytes());
+            for topic in event.topics.iter() {
+                buf.extend_from_slice(&(topic.len() as u32).to_le_bytes());
+                buf.extend_from_slice(topic);
+            }
+            buf.extend_from_slice(&(event.data.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&event.data);
+        }
+        buf
     }
 
     fn default_filestat() -> FileStat {
@@ -639,6 +1947,72 @@ impl BCFS {
     }
 }
 
+/// The storage of the home account overlaid with the writes staged by a `BCFS`, which are
+/// not visible to the transaction, or to the calls that it makes, until they are committed.
+/// Writes update the usage, if it has been computed.
+struct StagedState<'a> {
+    state: &'a dyn KVStore,
+    staged: &'a RefCell<BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+    usage: &'a Cell<Option<Usage>>,
+}
+
+impl StagedState<'_> {
+    fn stage(&mut self, key: &[u8], value: Option<&[u8]>) {
+        if let Some(mut usage) = self.usage.get() {
+            let prev_len = self.get(key).map(|prev_value| prev_value.len());
+            usage.update(key, prev_len, value.map(<[u8]>::len));
+            self.usage.set(Some(usage));
+        }
+        self.staged
+            .borrow_mut()
+            .insert(key.to_vec(), value.map(<[u8]>::to_vec));
+    }
+}
+
+impl KVStore for StagedState<'_> {
+    fn contains(&self, key: &[u8]) -> bool {
+        match self.staged.borrow().get(key) {
+            Some(value) => value.is_some(),
+            None => self.state.contains(key),
+        }
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        match self.staged.borrow().get(key) {
+            Some(value) => value.clone(),
+            None => self.state.get(key),
+        }
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Vec<Vec<u8>> {
+        let staged = self.staged.borrow();
+        let mut keys: BTreeSet<Vec<u8>> = self
+            .state
+            .scan_prefix(prefix)
+            .into_iter()
+            .filter(|key| !staged.contains_key(key))
+            .collect();
+        keys.extend(
+            staged
+                .range(prefix.to_vec()..)
+                .take_while(|(key, _)| key.starts_with(prefix))
+                .filter(|(_, value)| value.is_some())
+                .map(|(key, _)| key.clone()),
+        );
+        keys.into_iter().collect()
+    }
+}
+
+impl KVStoreMut for StagedState<'_> {
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        self.stage(key, Some(value));
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.stage(key, None);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,303 @@
+//! Chunked storage for large files.
+//!
+//! A chunked file is stored as an empty value at the file's key, which marks the existence of
+//! the file, a header at `header_key(key)`, and the file's contents split into chunks of
+//! `chunk_size` bytes at `chunk_key(key, index)`. Chunks that have never been written are
+//! absent and read as zeros. Reads and writes load and store only the chunks that they touch.
+//!
+//! The header is `chunk_size:u64 size:u64` (little-endian).
+
+use std::{cmp::min, collections::BTreeMap, convert::TryFrom as _};
+
+use blockchain_traits::{KVStore, KVStoreMut};
+use wasi_types::ErrNo;
+
+use crate::Result;
+
+/// The prefix of the keys of chunk headers. Chunk keys are prefixed by their header key.
+const HEADER_KEY_PREFIX: &[u8] = b"\0chunks/";
+
+pub struct ChunkedFile {
+    pub chunk_size: u64,
+
+    /// The size of the file, including changes that have not been flushed.
+    pub size: u64,
+
+    /// The offset of the next read or write.
+    pub position: u64,
+
+    /// The size of the file when it was last loaded or flushed.
+    stored_size: u64,
+
+    /// The smallest size to which the file has been truncated since it was last flushed.
+    /// Stored chunks past this size are stale.
+    truncated_size: u64,
+
+    /// The chunks that have been loaded, by index, and whether they have been modified.
+    /// Loaded chunks are always `chunk_size` long.
+    chunks: BTreeMap<u64, (Vec<u8>, bool)>,
+}
+
+impl ChunkedFile {
+    /// Returns a new, empty chunked file.
+    pub fn new(chunk_size: u64) -> Self {
+        Self {
+            chunk_size,
+            size: 0,
+            position: 0,
+            stored_size: 0,
+            truncated_size: 0,
+            chunks: BTreeMap::new(),
+        }
+    }
+
+    /// Loads the header of the chunked file at `key`. Returns `None` if the file is not chunked.
+    pub fn load(state: &dyn KVStore, key: &[u8]) -> Option<Self> {
+        let header = state.get(&header_key(key))?;
+        if header.len() != 16 {
+            return None;
+        }
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&header[..8]);
+        let chunk_size = u64::from_le_bytes(word);
+        word.copy_from_slice(&header[8..]);
+        let size = u64::from_le_bytes(word);
+        if chunk_size == 0 {
+            return None;
+        }
+        Some(Self {
+            size,
+            stored_size: size,
+            truncated_size: size,
+            ..Self::new(chunk_size)
+        })
+    }
+
+    /// Reads from `offset` into `buf` and returns the number of bytes read.
+    pub fn read(&mut self, state: &dyn KVStore, key: &[u8], buf: &mut [u8], offset: u64) -> usize {
+        let end = min(self.size, offset.saturating_add(buf.len() as u64));
+        let mut pos = offset;
+        while pos < end {
+            let (index, chunk_offset) = (pos / self.chunk_size, pos % self.chunk_size);
+            let nbytes = min(self.chunk_size - chunk_offset, end - pos);
+            let buf_offset = (pos - offset) as usize;
+            let chunk = self.chunk(state, key, index);
+            buf[buf_offset..(buf_offset + nbytes as usize)].copy_from_slice(
+                &chunk[(chunk_offset as usize)..((chunk_offset + nbytes) as usize)],
+            );
+            pos += nbytes;
+        }
+        (pos - offset) as usize
+    }
+
+    /// Writes `buf` at `offset`, extending the file if necessary, and returns the number of
+    /// bytes written. Returns `ErrNo::FBig` if the file would be larger than `u64::MAX` bytes.
+    pub fn write(
+        &mut self,
+        state: &dyn KVStore,
+        key: &[u8],
+        buf: &[u8],
+        offset: u64,
+    ) -> Result<usize> {
+        let end = offset.checked_add(buf.len() as u64).ok_or(ErrNo::FBig)?;
+        let mut pos = offset;
+        while pos < end {
+            let (index, chunk_offset) = (pos / self.chunk_size, pos % self.chunk_size);
+            let nbytes = min(self.chunk_size - chunk_offset, end - pos);
+            let buf_offset = (pos - offset) as usize;
+            if nbytes == self.chunk_size {
+                // The whole chunk is overwritten, so there's no need to load it.
+                self.chunks.insert(index, (Vec::new(), true));
+            }
+            let chunk = self.chunk_mut(state, key, index);
+            chunk[(chunk_offset as usize)..((chunk_offset + nbytes) as usize)]
+                .copy_from_slice(&buf[buf_offset..(buf_offset + nbytes as usize)]);
+            pos += nbytes;
+        }
+        self.size = self.size.max(end);
+        Ok(buf.len())
+    }
+
+    /// Truncates the file to `size` or extends it with zeros.
+    pub fn set_size(&mut self, state: &dyn KVStore, key: &[u8], size: u64) {
+        if size < self.size {
+            let num_chunks = self.num_chunks(size);
+            self.chunks.split_off(&num_chunks);
+            if size % self.chunk_size != 0 {
+                let chunk_offset = (size % self.chunk_size) as usize;
+                let chunk = self.chunk_mut(state, key, size / self.chunk_size);
+                chunk[chunk_offset..].iter_mut().for_each(|b| *b = 0);
+            }
+            self.truncated_size = min(self.truncated_size, size);
+        }
+        self.size = size;
+    }
+
+    /// Returns the keys and value lengths that will be written by `flush`.
+    pub fn dirty_entries(&self, key: &[u8]) -> Vec<(Vec<u8>, usize)> {
+        let mut entries = vec![(header_key(key), 16)];
+        for (index, (_, dirty)) in self.chunks.iter() {
+            if *dirty {
+                entries.push((chunk_key(key, *index), self.stored_chunk_len(*index)));
+            }
+        }
+        entries
+    }
+
+    /// Returns the keys and value lengths that would be written by `flush` after writing
+    /// `len` bytes at `offset`, or `ErrNo::FBig` if the write would be out of bounds.
+    pub fn write_entries(
+        &self,
+        key: &[u8],
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<(Vec<u8>, usize)>> {
+        let mut entries = self.dirty_entries(key);
+        if len == 0 {
+            return Ok(entries);
+        }
+        let end = offset.checked_add(len).ok_or(ErrNo::FBig)?;
+        let size = self.size.max(end);
+        for index in (offset / self.chunk_size)..self.num_chunks(end) {
+            let chunk_start = index * self.chunk_size;
+            let chunk_len = min(self.chunk_size, size - chunk_start) as usize;
+            let chunk_key = chunk_key(key, index);
+            match entries.iter_mut().find(|(k, _)| *k == chunk_key) {
+                Some((_, len)) => *len = chunk_len,
+                None => entries.push((chunk_key, chunk_len)),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Writes the modified chunks and the header of the file at `key` to `state`.
+    pub fn flush(&mut self, state: &mut dyn KVStoreMut, key: &[u8]) {
+        let prev_num_chunks = self.num_chunks(self.stored_size);
+        for index in self.num_chunks(self.truncated_size)..prev_num_chunks {
+            match self.chunks.get(&index) {
+                Some((_, true)) => (),
+                _ => state.remove(&chunk_key(key, index)), // The chunk is now stale.
+            }
+        }
+        for index in self.num_chunks(self.size)..prev_num_chunks {
+            state.remove(&chunk_key(key, index));
+        }
+
+        let dirty_chunks: Vec<u64> = self
+            .chunks
+            .iter()
+            .filter(|(_, (_, dirty))| *dirty)
+            .map(|(index, _)| *index)
+            .collect();
+        for index in dirty_chunks {
+            let chunk_len = self.stored_chunk_len(index);
+            let (chunk, dirty) = self.chunks.get_mut(&index).unwrap();
+            state.set(&chunk_key(key, index), &chunk[..chunk_len]);
+            *dirty = false;
+        }
+
+        let mut header = self.chunk_size.to_le_bytes().to_vec();
+        header.extend_from_slice(&self.size.to_le_bytes());
+        state.set(&header_key(key), &header);
+        self.stored_size = self.size;
+        self.truncated_size = self.size;
+    }
+
+    /// Returns the chunk at `index`, loading it from `state` if necessary.
+    fn chunk(&mut self, state: &dyn KVStore, key: &[u8], index: u64) -> &[u8] {
+        if !self.chunks.contains_key(&index) {
+            let chunk = self.load_chunk(state, key, index);
+            self.chunks.insert(index, (chunk, false));
+        }
+        &self.chunks[&index].0
+    }
+
+    /// Returns the chunk at `index` for modification, loading it from `state` if necessary.
+    fn chunk_mut(&mut self, state: &dyn KVStore, key: &[u8], index: u64) -> &mut [u8] {
+        let chunk_size = self.chunk_size as usize;
+        if !self.chunks.contains_key(&index) {
+            let chunk = self.load_chunk(state, key, index);
+            self.chunks.insert(index, (chunk, true));
+        }
+        let (chunk, dirty) = self.chunks.get_mut(&index).unwrap();
+        chunk.resize(chunk_size, 0);
+        *dirty = true;
+        chunk
+    }
+
+    fn load_chunk(&self, state: &dyn KVStore, key: &[u8], index: u64) -> Vec<u8> {
+        let chunk_start = index * self.chunk_size;
+        let mut chunk = if chunk_start < self.truncated_size {
+            state.get(&chunk_key(key, index)).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        let valid_len = min(
+            self.chunk_size,
+            self.truncated_size.saturating_sub(chunk_start),
+        );
+        chunk.truncate(valid_len as usize);
+        chunk.resize(self.chunk_size as usize, 0);
+        chunk
+    }
+
+    /// Returns the number of chunks in a file of `size` bytes.
+    fn num_chunks(&self, size: u64) -> u64 {
+        size / self.chunk_size + u64::from(size % self.chunk_size != 0)
+    }
+
+    /// Returns the number of bytes of the chunk at `index` that are within the file.
+    fn stored_chunk_len(&self, index: u64) -> usize {
+        let chunk_start = index * self.chunk_size;
+        usize::try_from(min(self.chunk_size, self.size.saturating_sub(chunk_start))).unwrap()
+    }
+}
+
+/// Removes the header and chunks of the file at `key`, if it is chunked.
+pub fn remove(state: &mut dyn KVStoreMut, key: &[u8]) {
+    for chunk_key in state.scan_prefix(&chunk_key_prefix(key)) {
+        state.remove(&chunk_key);
+    }
+    state.remove(&header_key(key));
+}
+
+/// Moves the header and chunks of the file at `key` to `new_key`, replacing those of the file
+/// at `new_key`, if any.
+pub fn rename(state: &mut dyn KVStoreMut, key: &[u8], new_key: &[u8]) {
+    remove(state, new_key);
+    let header = match state.get(&header_key(key)) {
+        Some(header) => header,
+        None => return,
+    };
+    let prefix = chunk_key_prefix(key);
+    let new_prefix = chunk_key_prefix(new_key);
+    for chunk_key in state.scan_prefix(&prefix) {
+        let chunk = state.get(&chunk_key).unwrap_or_default();
+        state.remove(&chunk_key);
+        let mut new_chunk_key = new_prefix.clone();
+        new_chunk_key.extend_from_slice(&chunk_key[prefix.len()..]);
+        state.set(&new_chunk_key, &chunk);
+    }
+    state.remove(&header_key(key));
+    state.set(&header_key(new_key), &header);
+}
+
+fn header_key(key: &[u8]) -> Vec<u8> {
+    let mut header_key = HEADER_KEY_PREFIX.to_vec();
+    header_key.extend_from_slice(key);
+    header_key
+}
+
+/// Returns the prefix of the keys of the chunks of the file at `key`. File keys can't contain
+/// NUL, so the prefix is not shared by the chunks of any other file.
+fn chunk_key_prefix(key: &[u8]) -> Vec<u8> {
+    let mut prefix = header_key(key);
+    prefix.push(0);
+    prefix
+}
+
+fn chunk_key(key: &[u8], index: u64) -> Vec<u8> {
+    let mut chunk_key = chunk_key_prefix(key);
+    chunk_key.extend_from_slice(&index.to_be_bytes());
+    chunk_key
+}
@@ -0,0 +1,51 @@
+use blockchain_traits::PendingTransaction;
+use oasis_types::env;
+
+/// The environment of a service, which contains the context of the current transaction in the
+/// variables read by `oasis_std::backend::wasi`.
+///
+/// The variables are laid out as they are by `environ_get`: each is `NAME=value\0`, and the
+/// variables are concatenated into a single buffer.
+pub struct Environ {
+    buf: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl Environ {
+    pub fn new(ptx: &dyn PendingTransaction) -> Self {
+        let vars = [
+            (env::ADDRESS, format!("{:x}", ptx.address())),
+            (env::SENDER, format!("{:x}", ptx.sender())),
+            (env::PAYER, format!("{:x}", ptx.payer())),
+            (env::VALUE, ptx.value().to_string()),
+            (env::AAD, base64::encode(ptx.aad())),
+        ];
+        let mut buf = Vec::new();
+        let mut offsets = Vec::with_capacity(vars.len());
+        for (name, value) in vars.iter() {
+            offsets.push(buf.len());
+            buf.extend_from_slice(name.as_bytes());
+            buf.push(b'=');
+            buf.extend_from_slice(value.as_bytes());
+            buf.push(0);
+        }
+        Self { buf, offsets }
+    }
+
+    /// Returns the number of variables and the size of the buffer that contains them,
+    /// as returned by `environ_sizes_get`.
+    pub fn sizes(&self) -> (usize, usize) {
+        (self.offsets.len(), self.buf.len())
+    }
+
+    /// Returns the buffer that contains the variables.
+    pub fn buf(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Returns the offset of each variable within `buf`. `environ_get` writes a pointer to
+    /// the start of each variable.
+    pub fn offsets(&self) -> &[usize] {
+        &self.offsets
+    }
+}
@@ -5,13 +5,21 @@ use std::{
 };
 
 use oasis_types::Address;
-use wasi_types::{FdFlags, FileStat};
+use wasi_types::{FdFlags, FileStat, Rights};
+
+use crate::chunked::ChunkedFile;
 
 pub struct File {
     pub kind: FileKind,
 
     pub flags: FdFlags,
 
+    /// The operations that may be performed on this fd.
+    pub rights: Rights,
+
+    /// The rights of fds opened through this fd. Only meaningful for directories.
+    pub rights_inheriting: Rights,
+
     /// File metadata cache.
     pub metadata: Cell<Option<FileStat>>,
 
@@ -25,6 +33,9 @@ pub struct File {
 pub enum FileCache {
     Absent(SeekFrom),
     Present(Cursor<Vec<u8>>),
+
+    /// A chunked file, of which only the chunks that have been accessed are cached.
+    Chunked(ChunkedFile),
 }
 
 pub enum FileKind {
@@ -32,10 +43,15 @@ pub enum FileKind {
     Stdout,
     Stderr,
     Log,
+    Events,
     Temporary,
     Regular { key: Vec<u8> },
     Balance { addr: Address },
     Bytecode { addr: Address },
+    Expiry { addr: Address },
+    CodeHash { addr: Address },
+    Nonce { addr: Address },
+    Interface { addr: Address },
     Directory { path: PathBuf },
 }
 
@@ -49,10 +65,92 @@ impl FileKind {
 
     pub fn is_blockchain_intrinsic(&self) -> bool {
         match self {
-            FileKind::Log | FileKind::Balance { .. } | FileKind::Bytecode { .. } => true,
+            FileKind::Log
+            | FileKind::Events
+            | FileKind::Balance { .. }
+            | FileKind::Bytecode { .. }
+            | FileKind::Expiry { .. }
+            | FileKind::CodeHash { .. }
+            | FileKind::Nonce { .. }
+            | FileKind::Interface { .. } => true,
             _ => false,
         }
     }
+
+    /// Returns the most rights that an fd to a file of this kind can have.
+    pub fn base_rights(&self) -> Rights {
+        match self {
+            FileKind::Stdin => {
+                Rights::FD_READ | Rights::FD_FILESTAT_GET | Rights::POLL_FD_READWRITE
+            }
+            FileKind::Stdout | FileKind::Stderr => {
+                Rights::FD_WRITE | Rights::FD_FILESTAT_GET | Rights::POLL_FD_READWRITE
+            }
+            // The log is append-only, so it can't be read or seeked.
+            FileKind::Log => {
+                Rights::FD_WRITE
+                    | Rights::FD_SYNC
+                    | Rights::FD_FILESTAT_GET
+                    | Rights::POLL_FD_READWRITE
+            }
+            FileKind::Events
+            | FileKind::Balance { .. }
+            | FileKind::Bytecode { .. }
+            | FileKind::Expiry { .. }
+            | FileKind::CodeHash { .. }
+            | FileKind::Nonce { .. }
+            | FileKind::Interface { .. } => {
+                Rights::FD_READ
+                    | Rights::FD_SEEK
+                    | Rights::FD_TELL
+                    | Rights::FD_FILESTAT_GET
+                    | Rights::POLL_FD_READWRITE
+            }
+            FileKind::Temporary | FileKind::Regular { .. } => file_rights(),
+            FileKind::Directory { .. } => dir_rights(),
+        }
+    }
+
+    /// Returns the most rights that fds opened through an fd to a file of this kind can have.
+    pub fn inheriting_rights(&self) -> Rights {
+        match self {
+            FileKind::Directory { .. } => dir_rights() | file_rights(),
+            _ => Rights::empty(),
+        }
+    }
+}
+
+fn file_rights() -> Rights {
+    Rights::FD_DATASYNC
+        | Rights::FD_READ
+        | Rights::FD_SEEK
+        | Rights::FD_FDSTAT_SET_FLAGS
+        | Rights::FD_SYNC
+        | Rights::FD_TELL
+        | Rights::FD_WRITE
+        | Rights::FD_ADVISE
+        | Rights::FD_ALLOCATE
+        | Rights::FD_FILESTAT_GET
+        | Rights::FD_FILESTAT_SET_SIZE
+        | Rights::FD_FILESTAT_SET_TIMES
+        | Rights::POLL_FD_READWRITE
+}
+
+fn dir_rights() -> Rights {
+    Rights::FD_FDSTAT_SET_FLAGS
+        | Rights::FD_SYNC
+        | Rights::FD_READDIR
+        | Rights::FD_FILESTAT_GET
+        | Rights::PATH_CREATE_DIRECTORY
+        | Rights::PATH_CREATE_FILE
+        | Rights::PATH_OPEN
+        | Rights::PATH_RENAME_SOURCE
+        | Rights::PATH_RENAME_TARGET
+        | Rights::PATH_FILESTAT_GET
+        | Rights::PATH_FILESTAT_SET_SIZE
+        | Rights::PATH_FILESTAT_SET_TIMES
+        | Rights::PATH_REMOVE_DIRECTORY
+        | Rights::PATH_UNLINK_FILE
 }
 
 macro_rules! special_file_ctor {
@@ -60,6 +158,8 @@ macro_rules! special_file_ctor {
         pub fn defaults(blockchain_name: &str) -> Vec<Option<Self>> {
             let mut chain_dir = PathBuf::from("/opt");
             chain_dir.push(blockchain_name);
+            let chain_dir = FileKind::Directory { path: chain_dir };
+            let home_dir = FileKind::Directory { path: PathBuf::from(".") };
 
             vec![
                 // Generate each of stdin, stdout, and stderr.
@@ -67,6 +167,8 @@ macro_rules! special_file_ctor {
                     Some(Self {
                         kind: FileKind::$kind,
                         flags: FdFlags::APPEND | FdFlags::SYNC,
+                        rights: FileKind::$kind.base_rights(),
+                        rights_inheriting: Rights::empty(),
                         metadata: Cell::new(None),
                         buf: RefCell::new(FileCache::Absent(SeekFrom::Start(0))),
                         dirty: Cell::new(false),
@@ -78,8 +180,10 @@ macro_rules! special_file_ctor {
                 // This capability (and all other directory caps) will be discovered when
                 // the WASI libc calls `fd_prestat_get` during the `_start` function.
                 Some(Self {
-                    kind: FileKind::Directory { path: chain_dir },
                     flags: FdFlags::SYNC,
+                    rights: chain_dir.base_rights(),
+                    rights_inheriting: chain_dir.inheriting_rights(),
+                    kind: chain_dir,
                     metadata: Cell::new(None),
                     buf: RefCell::new(FileCache::Absent(SeekFrom::Start(0))),
                     dirty: Cell::new(false),
@@ -87,8 +191,10 @@ macro_rules! special_file_ctor {
 
                 // This fd is the capability to the service's home directory.
                 Some(Self {
-                    kind: FileKind::Directory { path: PathBuf::from(".") },
                     flags: FdFlags::SYNC,
+                    rights: home_dir.base_rights(),
+                    rights_inheriting: home_dir.inheriting_rights(),
+                    kind: home_dir,
                     metadata: Cell::new(None),
                     buf: RefCell::new(FileCache::Absent(SeekFrom::Start(0))),
                     dirty: Cell::new(false),
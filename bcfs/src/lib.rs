@@ -1,9 +1,16 @@
 type Result<T> = std::result::Result<T, wasi_types::ErrNo>;
 
 mod bcfs;
+mod chunked;
+mod environ;
 mod file;
+mod random;
+mod wasm;
 
-pub use crate::bcfs::BCFS;
+pub use crate::{
+    bcfs::{Quota, Usage, BCFS, DEFAULT_MAX_FDS},
+    environ::Environ,
+};
 
 #[cfg(test)]
 mod tests;
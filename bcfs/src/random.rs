@@ -0,0 +1,74 @@
+//! Deterministic randomness for `random_get`.
+//!
+//! Every node that executes a transaction must compute the same result, so services get a
+//! stream of bytes derived from the transaction instead of host entropy. The stream is
+//! keccak256 in counter mode under a seed that depends on the block (its height and timestamp,
+//! since blocks expose no hash to a pending transaction), the transaction (its sender, payer,
+//! value and input, and the nonces of the sender and payer, which distinguish otherwise
+//! identical transactions) and the callee. The stream is therefore predictable by anyone who
+//! can predict those and must not be used to generate secrets.
+
+use std::cmp::min;
+
+use blockchain_traits::PendingTransaction;
+
+const BLOCK_SIZE: u64 = 32;
+
+pub struct RandomStream {
+    seed: [u8; 32],
+
+    /// The number of bytes that have been read from the stream.
+    position: u64,
+}
+
+impl RandomStream {
+    /// Returns the stream of the transaction being executed by `ptx`.
+    pub fn new(ptx: &dyn PendingTransaction) -> Self {
+        let nonce = |addr| {
+            ptx.account_meta_at(addr)
+                .map(|meta| meta.nonce)
+                .unwrap_or_default()
+        };
+        Self {
+            seed: hash(&[
+                b"random",
+                &ptx.block_height().to_le_bytes(),
+                &ptx.block_timestamp().to_le_bytes(),
+                ptx.sender().as_ref(),
+                &nonce(ptx.sender()).to_le_bytes(),
+                ptx.payer().as_ref(),
+                &nonce(ptx.payer()).to_le_bytes(),
+                &ptx.value().to_le_bytes(),
+                ptx.input(),
+                ptx.address().as_ref(),
+            ]),
+            position: 0,
+        }
+    }
+
+    /// Fills `buf` with the next bytes of the stream.
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        let mut nfilled = 0;
+        while nfilled < buf.len() {
+            let block = hash(&[&self.seed, &(self.position / BLOCK_SIZE).to_le_bytes()]);
+            let block_offset = (self.position % BLOCK_SIZE) as usize;
+            let nbytes = min(block.len() - block_offset, buf.len() - nfilled);
+            buf[nfilled..(nfilled + nbytes)]
+                .copy_from_slice(&block[block_offset..(block_offset + nbytes)]);
+            nfilled += nbytes;
+            self.position += nbytes as u64;
+        }
+    }
+}
+
+/// Hashes the length-prefixed concatenation of `parts`.
+fn hash(parts: &[&[u8]]) -> [u8; 32] {
+    let mut keccak = tiny_keccak::Keccak::new_keccak256();
+    for part in parts {
+        keccak.update(&(part.len() as u64).to_le_bytes());
+        keccak.update(part);
+    }
+    let mut digest = [0u8; 32];
+    keccak.finalize(&mut digest);
+    digest
+}
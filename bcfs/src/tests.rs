@@ -1,20 +1,21 @@
 #![cfg(test)]
 
 use std::{
-    borrow::Cow,
     collections::HashMap,
     io::{IoSlice, IoSliceMut},
     path::{Path, PathBuf},
 };
 
-use blockchain_traits::{Blockchain, TransactionOutcome};
+use blockchain_traits::{Block, Blockchain, PendingTransaction, TransactionOutcome};
 use memchain::{Account, Memchain};
 use oasis_types::{Address, Event};
-use wasi_types::{ErrNo, Fd, FdFlags, FileType, OpenFlags, Whence};
+use wasi_types::{
+    ClockId, ErrNo, Fd, FdFlags, FileType, FstFlags, OpenFlags, Rights, Timestamp, Whence,
+};
 
 use crate::{
     file::{CHAIN_DIR_FILENO, HOME_DIR_FILENO},
-    BCFS,
+    Environ, Quota, Result, BCFS,
 };
 
 macro_rules! chain_name {
@@ -28,6 +29,7 @@ macro_rules! chain_name {
 
 const ADDR_1: Address = Address([1u8; 20]);
 const ADDR_2: Address = Address([2u8; 20]);
+const ADDR_3: Address = Address([3u8; 20]);
 const BASE_GAS: u64 = 2100;
 const GAS_PRICE: u64 = 0;
 const CHAIN_NAME: &str = chain_name!();
@@ -36,7 +38,7 @@ fn giga(val: u128) -> u128 {
     val * 1_000_000_000
 }
 
-fn create_memchain(mains: Vec<Option<memchain::AccountMain>>) -> Memchain<'static> {
+fn create_memchain(mains: Vec<Option<memchain::AccountMain>>) -> Memchain {
     let genesis_state = mains
         .into_iter()
         .enumerate()
@@ -44,7 +46,7 @@ fn create_memchain(mains: Vec<Option<memchain::AccountMain>>) -> Memchain<'stati
             let i = i + 1;
             (
                 Address([i as u8; 20]),
-                Cow::Owned(Account {
+                Account {
                     balance: giga(i as u128),
                     code: format!("\0asm not wasm {}", i).into_bytes(),
                     storage: {
@@ -59,9 +61,12 @@ fn create_memchain(mains: Vec<Option<memchain::AccountMain>>) -> Memchain<'stati
                         );
                         storage
                     },
+                    storage_deposits: HashMap::new(),
                     expiry: None,
+                    nonce: 0,
+                    confidential_key: None,
                     main,
-                }),
+                },
             )
         })
         .collect();
@@ -79,8 +84,7 @@ macro_rules! testcase {
     (fn $fn_name:ident ( $ptx:ident : &mut dyn PendingTransaction ) $body:block) => {
         #[test]
         fn $fn_name() {
-            extern "C" fn test_main(ptxp: memchain::PtxPtr) -> u16 {
-                let $ptx = unsafe { &mut **ptxp };
+            fn test_main($ptx: &mut dyn PendingTransaction) -> u16 {
                 $body
                 0
             }
@@ -126,6 +130,8 @@ testcase!(
                 crate::file::CHAIN_DIR_FILENO.into(),
                 &abspath,
                 OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty(),
             )
             .unwrap();
@@ -137,6 +143,8 @@ testcase!(
                 crate::file::CHAIN_DIR_FILENO.into(),
                 &abspath,
                 OpenFlags::EXCL,
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty()
             ),
             Err(ErrNo::Exist)
@@ -148,6 +156,8 @@ testcase!(
                 crate::file::CHAIN_DIR_FILENO.into(),
                 &abspath,
                 OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty(),
             )
             .unwrap();
@@ -157,6 +167,8 @@ testcase!(
                 crate::file::HOME_DIR_FILENO.into(),
                 &relpath,
                 OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
                 FdFlags::APPEND,
             )
             .unwrap();
@@ -179,6 +191,8 @@ testcase!(
                 crate::file::HOME_DIR_FILENO.into(),
                 &path,
                 OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty(),
             )
             .unwrap();
@@ -288,6 +302,8 @@ testcase!(
                 crate::file::HOME_DIR_FILENO.into(),
                 &path,
                 OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty(),
             )
             .unwrap();
@@ -348,6 +364,8 @@ testcase!(
                 crate::file::HOME_DIR_FILENO.into(),
                 &path,
                 OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty(),
             )
             .unwrap();
@@ -357,6 +375,8 @@ testcase!(
                 crate::file::CHAIN_DIR_FILENO.into(),
                 &abspath,
                 OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty(),
             )
             .unwrap();
@@ -470,6 +490,8 @@ testcase!(
                 crate::file::HOME_DIR_FILENO.into(),
                 &somefile,
                 OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty(),
             )
             .unwrap();
@@ -479,6 +501,8 @@ testcase!(
                 crate::file::HOME_DIR_FILENO.into(),
                 &anotherfile,
                 OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty(),
             )
             .unwrap();
@@ -526,7 +550,15 @@ testcase!(
         let curdir = crate::file::HOME_DIR_FILENO.into();
 
         let fd = bcfs
-            .open(ptx, curdir, &path, OpenFlags::CREATE, FdFlags::empty())
+            .open(
+                ptx,
+                curdir,
+                &path,
+                OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
             .unwrap();
 
         let write_val = b"not empty";
@@ -537,7 +569,15 @@ testcase!(
         assert!(bcfs.close(ptx, fd).is_ok());
         assert_eq!(bcfs.unlink(ptx, curdir, &path), Ok(write_val.len() as u64));
         assert_eq!(
-            bcfs.open(ptx, curdir, &path, OpenFlags::empty(), FdFlags::empty()),
+            bcfs.open(
+                ptx,
+                curdir,
+                &path,
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty()
+            ),
             Err(ErrNo::NoEnt)
         );
     }
@@ -555,7 +595,15 @@ macro_rules! write_twice {
 
         let mut do_write = |val: &str| {
             let fd = bcfs
-                .open($ptx, curdir, &path, OpenFlags::CREATE | $oflags, $fdflags)
+                .open(
+                    $ptx,
+                    curdir,
+                    &path,
+                    OpenFlags::CREATE | $oflags,
+                    Rights::all(),
+                    Rights::all(),
+                    $fdflags,
+                )
                 .unwrap();
             bcfs.write_vectored($ptx, fd, &[std::io::IoSlice::new(val.as_bytes())])
                 .unwrap();
@@ -566,7 +614,15 @@ macro_rules! write_twice {
         do_write(second);
 
         let fd = bcfs
-            .open($ptx, curdir, &path, OpenFlags::empty(), FdFlags::empty())
+            .open(
+                $ptx,
+                curdir,
+                &path,
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
             .unwrap();
 
         let mut read_buf = vec![' ' as u8; first.len() + second.len()];
@@ -640,6 +696,8 @@ testcase!(
                 Fd::from(0u32 /* stdin */),
                 &Path::new("file.txt"),
                 OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty()
             ),
             Err(ErrNo::BadF)
@@ -652,6 +710,8 @@ testcase!(
                 HOME_DIR_FILENO.into(),
                 &Path::new(chain_name!(opt)),
                 OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty()
             ),
             Err(ErrNo::NoEnt)
@@ -664,6 +724,8 @@ testcase!(
                 HOME_DIR_FILENO.into(),
                 &Path::new("../../../asdf"),
                 OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty()
             ),
             Err(ErrNo::NoEnt)
@@ -676,6 +738,8 @@ testcase!(
                 HOME_DIR_FILENO.into(),
                 &Path::new(""),
                 OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty()
             ),
             Err(ErrNo::Inval)
@@ -688,6 +752,8 @@ testcase!(
                 CHAIN_DIR_FILENO.into(),
                 &Path::new("not-log"),
                 OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty()
             ),
             Err(ErrNo::NoEnt)
@@ -710,14 +776,30 @@ testcase!(
             .iter()
             {
                 assert_eq!(
-                    bcfs.open(ptx, *dir, &Path::new(f), *oflags, FdFlags::empty()),
+                    bcfs.open(
+                        ptx,
+                        *dir,
+                        &Path::new(f),
+                        *oflags,
+                        Rights::all(),
+                        Rights::all(),
+                        FdFlags::empty()
+                    ),
                     Err(ErrNo::Exist)
                 );
             }
 
             // Special files cannot be truncated.
             assert_eq!(
-                bcfs.open(ptx, *dir, &Path::new(f), OpenFlags::TRUNC, FdFlags::empty()),
+                bcfs.open(
+                    ptx,
+                    *dir,
+                    &Path::new(f),
+                    OpenFlags::TRUNC,
+                    Rights::all(),
+                    Rights::all(),
+                    FdFlags::empty()
+                ),
                 Err(ErrNo::Inval)
             );
 
@@ -732,6 +814,8 @@ testcase!(
                 CHAIN_DIR_FILENO.into(),
                 &Path::new("log"),
                 OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty()
             ),
             Err(ErrNo::Inval)
@@ -741,9 +825,7 @@ testcase!(
 
 #[test]
 fn tempfile() {
-    extern "C" fn test_main(ptxp: memchain::PtxPtr) -> u16 {
-        let ptx = unsafe { &mut **ptxp };
-
+    fn test_main(ptx: &mut dyn PendingTransaction) -> u16 {
         let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
 
         let temp_fd = bcfs.tempfile(ptx).unwrap();
@@ -805,9 +887,7 @@ fn regular_file() {
     }
     let file_contents = b"input";
 
-    extern "C" fn test_main(ptxp: memchain::PtxPtr) -> u16 {
-        let ptx = unsafe { &mut **ptxp };
-
+    fn test_main(ptx: &mut dyn PendingTransaction) -> u16 {
         let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
 
         let input = ptx.input().to_vec();
@@ -822,6 +902,8 @@ fn regular_file() {
                 } else {
                     OpenFlags::CREATE
                 },
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty(),
             )
             .unwrap();
@@ -830,6 +912,7 @@ fn regular_file() {
             .unwrap();
 
         bcfs.flush(ptx, fd).unwrap();
+        bcfs.commit(ptx).unwrap();
 
         0
     }
@@ -898,6 +981,8 @@ testcase!(
                     HOME_DIR_FILENO.into(),
                     &Path::new(f),
                     OpenFlags::empty(),
+                    Rights::all(),
+                    Rights::all(),
                     FdFlags::empty(),
                 )
                 .unwrap();
@@ -909,6 +994,8 @@ testcase!(
                 CHAIN_DIR_FILENO.into(),
                 &Path::new("log"),
                 OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
                 FdFlags::APPEND,
             )
             .unwrap();
@@ -918,8 +1005,7 @@ testcase!(
 
 #[test]
 fn flush_log_to_ptx() {
-    extern "C" fn test_main(ptxp: memchain::PtxPtr) -> u16 {
-        let ptx = unsafe { &mut **ptxp };
+    fn test_main(ptx: &mut dyn PendingTransaction) -> u16 {
         let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
 
         let log_fd = bcfs
@@ -928,6 +1014,8 @@ fn flush_log_to_ptx() {
                 CHAIN_DIR_FILENO.into(),
                 &Path::new("log"),
                 OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
                 FdFlags::APPEND,
             )
             .unwrap();
@@ -974,8 +1062,7 @@ fn flush_log_to_ptx() {
 fn flush_output_to_ptx() {
     const OUTPUT: &[u8] = b"output";
 
-    extern "C" fn test_main(ptxp: memchain::PtxPtr) -> u16 {
-        let ptx = unsafe { &mut **ptxp };
+    fn test_main(ptx: &mut dyn PendingTransaction) -> u16 {
         let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
 
         let stdout_fd = Fd::from(1u32);
@@ -1010,6 +1097,8 @@ testcase!(
                     dir_fd,
                     &Path::new(name),
                     OpenFlags::empty(),
+                    Rights::all(),
+                    Rights::all(),
                     FdFlags::empty(),
                 )
                 .unwrap();
@@ -1039,7 +1128,7 @@ testcase!(
             let mut read_buf = vec![0u8; 0];
             assert_eq!(
                 bcfs.read_vectored(ptx, i.into(), &mut [IoSliceMut::new(&mut read_buf)]),
-                Err(ErrNo::Inval)
+                Err(ErrNo::NotCapable)
             );
         }
     }
@@ -1057,6 +1146,8 @@ testcase!(
                 crate::file::HOME_DIR_FILENO.into(),
                 &path,
                 OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
                 FdFlags::empty(),
             )
             .unwrap();
@@ -1073,6 +1164,8 @@ testcase!(
                     crate::file::HOME_DIR_FILENO.into(),
                     &path,
                     OpenFlags::empty(),
+                    Rights::all(),
+                    Rights::all(),
                     FdFlags::empty(),
                 )
                 .unwrap();
@@ -1138,3 +1231,1601 @@ pub fn create_log() -> (Vec<Vec<u8>>, Vec<u8>, Vec<u8>) {
 
     (topics, data, log)
 }
+
+/// Parses the output of `BCFS::readdir` into (d_next, name, d_type) tuples.
+fn parse_dirents(mut buf: &[u8]) -> Vec<(u64, String, u8)> {
+    let mut dirents = Vec::new();
+    while buf.len() >= 24 {
+        let mut u64_bytes = [0u8; 8];
+        u64_bytes.copy_from_slice(&buf[..8]);
+        let d_next = u64::from_le_bytes(u64_bytes);
+        let mut u32_bytes = [0u8; 4];
+        u32_bytes.copy_from_slice(&buf[16..20]);
+        let namlen = u32::from_le_bytes(u32_bytes) as usize;
+        let d_type = buf[20];
+        let name = String::from_utf8(buf[24..(24 + namlen)].to_vec()).unwrap();
+        dirents.push((d_next, name, d_type));
+        buf = &buf[(24 + namlen)..];
+    }
+    dirents
+}
+
+testcase!(
+    fn directories(ptx: &mut dyn PendingTransaction) {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
+        let home = HOME_DIR_FILENO.into();
+
+        assert_eq!(bcfs.create_directory(ptx, home, Path::new("dir")), Ok(()));
+        assert_eq!(
+            bcfs.create_directory(ptx, home, Path::new("dir")),
+            Err(ErrNo::Exist)
+        );
+        assert_eq!(
+            bcfs.create_directory(ptx, home, Path::new("dir/sub")),
+            Ok(())
+        );
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("dir/file"),
+                OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        bcfs.close(ptx, fd).unwrap();
+
+        let dir_fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("dir"),
+                OpenFlags::DIRECTORY,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        assert_eq!(
+            bcfs.filestat(ptx, dir_fd).unwrap().file_type,
+            FileType::Directory
+        );
+
+        let mut buf = vec![0u8; 1024];
+        let nbytes = bcfs.readdir(ptx, dir_fd, &mut buf, 0).unwrap();
+        let dirents = parse_dirents(&buf[..nbytes]);
+        assert_eq!(
+            dirents,
+            vec![
+                (1, ".".to_string(), FileType::Directory as u8),
+                (2, "..".to_string(), FileType::Directory as u8),
+                (3, "file".to_string(), FileType::RegularFile as u8),
+                (4, "sub".to_string(), FileType::Directory as u8),
+            ]
+        );
+
+        // Resuming from a cookie.
+        let nbytes = bcfs.readdir(ptx, dir_fd, &mut buf, 3).unwrap();
+        assert_eq!(parse_dirents(&buf[..nbytes]), dirents[3..].to_vec());
+
+        // Truncating entries that don't fit in the buffer.
+        let mut small_buf = vec![0u8; 30];
+        assert_eq!(bcfs.readdir(ptx, dir_fd, &mut small_buf, 0), Ok(30));
+
+        // Opening files relative to a directory fd.
+        assert!(bcfs
+            .open(
+                ptx,
+                dir_fd,
+                Path::new("file"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty()
+            )
+            .is_ok());
+
+        assert_eq!(
+            bcfs.open(
+                ptx,
+                home,
+                Path::new("dir"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty()
+            ),
+            Err(ErrNo::IsDir)
+        );
+        assert_eq!(
+            bcfs.open(
+                ptx,
+                home,
+                Path::new("dir/file"),
+                OpenFlags::DIRECTORY,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty()
+            ),
+            Err(ErrNo::NotDir)
+        );
+        assert_eq!(bcfs.unlink(ptx, home, Path::new("dir")), Err(ErrNo::IsDir));
+
+        assert_eq!(
+            bcfs.remove_directory(ptx, home, Path::new("dir")),
+            Err(ErrNo::NotEmpty)
+        );
+        assert_eq!(
+            bcfs.remove_directory(ptx, home, Path::new("dir/file")),
+            Err(ErrNo::NotDir)
+        );
+        assert_eq!(bcfs.unlink(ptx, dir_fd, Path::new("file")), Ok(0));
+        assert_eq!(
+            bcfs.remove_directory(ptx, home, Path::new("dir/sub")),
+            Ok(())
+        );
+        assert_eq!(bcfs.remove_directory(ptx, home, Path::new("dir")), Ok(()));
+        assert_eq!(
+            bcfs.open(
+                ptx,
+                home,
+                Path::new("dir"),
+                OpenFlags::DIRECTORY,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty()
+            ),
+            Err(ErrNo::NoEnt)
+        );
+
+        // The home directory contains the keys in the account's genesis storage.
+        let home_fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("."),
+                OpenFlags::DIRECTORY,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        let nbytes = bcfs.readdir(ptx, home_fd, &mut buf, 2).unwrap();
+        let names: Vec<String> = parse_dirents(&buf[..nbytes])
+            .into_iter()
+            .map(|(_, name, _)| name)
+            .collect();
+        assert_eq!(names, vec!["common_key", "key_2"]);
+    }
+);
+
+fn read_file(bcfs: &mut BCFS, ptx: &mut dyn PendingTransaction, path: &str) -> Vec<u8> {
+    let fd = bcfs
+        .open(
+            ptx,
+            HOME_DIR_FILENO.into(),
+            Path::new(path),
+            OpenFlags::empty(),
+            Rights::all(),
+            Rights::all(),
+            FdFlags::empty(),
+        )
+        .unwrap();
+    let mut buf = vec![0u8; 64];
+    let nbytes = bcfs
+        .read_vectored(ptx, fd, &mut [IoSliceMut::new(&mut buf)])
+        .unwrap();
+    bcfs.close(ptx, fd).unwrap();
+    buf.truncate(nbytes);
+    buf
+}
+
+testcase!(
+    fn rename(ptx: &mut dyn PendingTransaction) {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
+        let home = HOME_DIR_FILENO.into();
+
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("tmp"),
+                OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"hello")])
+            .unwrap();
+        let replaced_fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("common_key"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        bcfs.write_vectored(ptx, replaced_fd, &[IoSlice::new(b"stale")])
+            .unwrap();
+
+        // The unflushed write is moved along with the file, which replaces `common_key`.
+        assert_eq!(
+            bcfs.rename(ptx, home, Path::new("tmp"), home, Path::new("common_key")),
+            Ok(())
+        );
+        assert_eq!(read_file(&mut bcfs, ptx, "common_key"), b"hello");
+
+        // The file that was open at `common_key` was closed without writing back its changes.
+        assert_eq!(bcfs.close(ptx, replaced_fd), Err(ErrNo::BadF));
+        assert_eq!(read_file(&mut bcfs, ptx, "common_key"), b"hello");
+        assert_eq!(
+            bcfs.open(
+                ptx,
+                home,
+                Path::new("tmp"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty()
+            ),
+            Err(ErrNo::NoEnt)
+        );
+
+        // The open file follows the rename.
+        bcfs.write_vectored(ptx, fd, &[IoSlice::new(b", world")])
+            .unwrap();
+        bcfs.close(ptx, fd).unwrap();
+        assert_eq!(read_file(&mut bcfs, ptx, "common_key"), b"hello, world");
+
+        // Directories are moved along with their contents.
+        bcfs.create_directory(ptx, home, Path::new("dir")).unwrap();
+        bcfs.create_directory(ptx, home, Path::new("new_dir"))
+            .unwrap();
+        assert_eq!(
+            bcfs.rename(
+                ptx,
+                home,
+                Path::new("common_key"),
+                home,
+                Path::new("dir/file")
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            bcfs.rename(ptx, home, Path::new("dir"), home, Path::new("dir/sub")),
+            Err(ErrNo::Inval)
+        );
+        assert_eq!(
+            bcfs.rename(ptx, home, Path::new("dir"), home, Path::new("key_2")),
+            Err(ErrNo::NotDir)
+        );
+        assert_eq!(
+            bcfs.rename(ptx, home, Path::new("key_2"), home, Path::new("dir")),
+            Err(ErrNo::IsDir)
+        );
+        assert_eq!(
+            bcfs.rename(ptx, home, Path::new("dir"), home, Path::new("new_dir")),
+            Ok(())
+        );
+        assert_eq!(read_file(&mut bcfs, ptx, "new_dir/file"), b"hello, world");
+        assert_eq!(
+            bcfs.remove_directory(ptx, home, Path::new("dir")),
+            Err(ErrNo::NoEnt)
+        );
+
+        assert_eq!(
+            bcfs.rename(ptx, home, Path::new("missing"), home, Path::new("file")),
+            Err(ErrNo::NoEnt)
+        );
+        assert_eq!(
+            bcfs.rename(ptx, home, Path::new("key_2"), home, Path::new("balance")),
+            Err(ErrNo::Access)
+        );
+    }
+);
+
+testcase!(
+    fn set_size(ptx: &mut dyn PendingTransaction) {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
+        let home = HOME_DIR_FILENO.into();
+
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("key_2"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        assert_eq!(bcfs.set_size(ptx, fd, 3), Ok(()));
+        assert_eq!(bcfs.filestat(ptx, fd).unwrap().file_size, 3);
+        bcfs.close(ptx, fd).unwrap();
+        assert_eq!(read_file(&mut bcfs, ptx, "key_2"), b"val");
+
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("key_2"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        assert_eq!(bcfs.seek(ptx, fd, 2, Whence::Start), Ok(2));
+        assert_eq!(bcfs.set_size(ptx, fd, 5), Ok(()));
+        assert_eq!(bcfs.tell(ptx, fd), Ok(2));
+        bcfs.close(ptx, fd).unwrap();
+        assert_eq!(read_file(&mut bcfs, ptx, "key_2"), b"val\0\0");
+
+        assert_eq!(
+            bcfs.set_size(ptx, Fd::from(0u32 /* stdin */), 0),
+            Err(ErrNo::NotCapable)
+        );
+
+        let usage = bcfs.usage(ptx);
+        let mut bcfs = bcfs.with_quota(Quota {
+            max_bytes: Some(usage.bytes + 10),
+            max_keys: None,
+        });
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("key_2"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        assert_eq!(bcfs.set_size(ptx, fd, 1 << 40), Err(ErrNo::FBig));
+        assert_eq!(bcfs.set_size(ptx, fd, 1 << 20), Err(ErrNo::DQuot));
+        assert_eq!(bcfs.filestat(ptx, fd).unwrap().file_size, 5);
+        assert_eq!(bcfs.set_size(ptx, fd, 15), Ok(()));
+        bcfs.close(ptx, fd).unwrap();
+        assert_eq!(read_file(&mut bcfs, ptx, "key_2").len(), 15);
+    }
+);
+
+testcase!(
+    fn set_times(ptx: &mut dyn PendingTransaction) {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
+        let home = HOME_DIR_FILENO.into();
+
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("key_2"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        assert_eq!(
+            bcfs.set_times(
+                ptx,
+                home,
+                Path::new("key_2"),
+                1u64.into(),
+                2u64.into(),
+                FstFlags::MTIM
+            ),
+            Ok(())
+        );
+        let filestat = bcfs.filestat(ptx, fd).unwrap();
+        assert_eq!(filestat.mtime, Timestamp::from(2u64));
+        assert_eq!(filestat.atime, Timestamp::from(0u64));
+
+        assert_eq!(
+            bcfs.set_times(
+                ptx,
+                home,
+                Path::new("key_2"),
+                1u64.into(),
+                2u64.into(),
+                FstFlags::MTIM | FstFlags::MTIM_NOW
+            ),
+            Err(ErrNo::Inval)
+        );
+        assert_eq!(
+            bcfs.set_times(
+                ptx,
+                home,
+                Path::new("missing"),
+                1u64.into(),
+                2u64.into(),
+                FstFlags::ATIM
+            ),
+            Err(ErrNo::NoEnt)
+        );
+    }
+);
+
+#[test]
+fn filestat_times() {
+    fn test_main(ptx: &mut dyn PendingTransaction) -> u16 {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
+        let home = HOME_DIR_FILENO.into();
+        let now = Timestamp::from(ptx.block_timestamp());
+
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("file"),
+                OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        let created = now == Timestamp::from(1000u64);
+        if !created {
+            bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"hello")])
+                .unwrap();
+        }
+        bcfs.close(ptx, fd).unwrap();
+
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("file"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        let filestat = bcfs.filestat(ptx, fd).unwrap();
+        assert_eq!(filestat.mtime, now);
+        assert_eq!(filestat.atime, now);
+        assert_eq!(filestat.ctime, Timestamp::from(1000u64));
+        assert_ne!(filestat.inode, 0u64.into());
+
+        let other_fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("key_2"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        assert_ne!(bcfs.filestat(ptx, other_fd).unwrap().inode, filestat.inode);
+
+        assert_eq!(
+            bcfs.set_times(
+                ptx,
+                home,
+                Path::new("key_2"),
+                0u64.into(),
+                0u64.into(),
+                FstFlags::ATIM_NOW | FstFlags::MTIM
+            ),
+            Ok(())
+        );
+        let other_filestat = bcfs.filestat(ptx, other_fd).unwrap();
+        assert_eq!(other_filestat.atime, now);
+        assert_eq!(other_filestat.mtime, Timestamp::from(0u64));
+        bcfs.commit(ptx).unwrap();
+        0
+    }
+
+    let mut bc = create_memchain(vec![None, Some(test_main)]);
+    for timestamp in &[1000, 2000] {
+        let receipt = bc.create_block_with_timestamp(*timestamp).transact(
+            ADDR_1, ADDR_2, ADDR_1, /* payer */
+            0,      /* value */
+            b"input", BASE_GAS, GAS_PRICE,
+        );
+        assert_eq!(receipt.outcome(), TransactionOutcome::Success);
+    }
+}
+
+testcase!(
+    fn fd_recycling(ptx: &mut dyn PendingTransaction) {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME).with_max_fds(HOME_DIR_FILENO + 3);
+        let first_fd = Fd::from(HOME_DIR_FILENO + 1);
+
+        fn open(bcfs: &mut BCFS, ptx: &mut dyn PendingTransaction, path: &str) -> Result<Fd> {
+            bcfs.open(
+                ptx,
+                HOME_DIR_FILENO.into(),
+                Path::new(path),
+                OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+        }
+
+        for _ in 0..10 {
+            let fd = open(&mut bcfs, ptx, "key_2").unwrap();
+            assert_eq!(fd, first_fd);
+            bcfs.close(ptx, fd).unwrap();
+        }
+
+        let fd = open(&mut bcfs, ptx, "key_2").unwrap();
+        let next_fd = open(&mut bcfs, ptx, "common_key").unwrap();
+        assert_eq!(u32::from(next_fd), HOME_DIR_FILENO + 2);
+        assert_eq!(open(&mut bcfs, ptx, "newfile"), Err(ErrNo::MFile));
+        assert!(!ptx.state().contains(b"newfile"));
+        assert_eq!(bcfs.tempfile(ptx), Err(ErrNo::MFile));
+
+        bcfs.close(ptx, fd).unwrap();
+        assert_eq!(open(&mut bcfs, ptx, "newfile"), Ok(first_fd));
+
+        // The lowest closed fd is reused, even if it was pre-opened.
+        bcfs.close(ptx, Fd::from(0u32 /* stdin */)).unwrap();
+        assert_eq!(bcfs.tempfile(ptx), Ok(Fd::from(0u32)));
+    }
+);
+
+testcase!(
+    fn rights(ptx: &mut dyn PendingTransaction) {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
+        let home = HOME_DIR_FILENO.into();
+
+        let log_fd = bcfs
+            .open(
+                ptx,
+                CHAIN_DIR_FILENO.into(),
+                Path::new("log"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::APPEND,
+            )
+            .unwrap();
+        let mut read_buf = vec![0u8; 1];
+        assert_eq!(
+            bcfs.read_vectored(ptx, log_fd, &mut [IoSliceMut::new(&mut read_buf)]),
+            Err(ErrNo::NotCapable)
+        );
+        assert_eq!(
+            bcfs.seek(ptx, log_fd, 0, Whence::Start),
+            Err(ErrNo::NotCapable)
+        );
+
+        let balance_fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("balance"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        let balance_rights = bcfs.fdstat(ptx, balance_fd).unwrap().rights_base;
+        assert!(balance_rights.contains(Rights::FD_READ));
+        assert!(!balance_rights.contains(Rights::FD_WRITE));
+        assert_eq!(
+            bcfs.write_vectored(ptx, balance_fd, &[IoSlice::new(b"1")]),
+            Err(ErrNo::NotCapable)
+        );
+
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("key_2"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        let file_rights = bcfs.fdstat(ptx, fd).unwrap().rights_base;
+        assert_eq!(
+            bcfs.set_rights(ptx, fd, file_rights - Rights::FD_WRITE, Rights::empty()),
+            Ok(())
+        );
+        assert_eq!(
+            bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"1")]),
+            Err(ErrNo::NotCapable)
+        );
+        assert_eq!(
+            bcfs.set_rights(ptx, fd, file_rights, Rights::empty()),
+            Err(ErrNo::NotCapable) // rights can't be regained
+        );
+
+        // Files have only the requested rights, which must include those needed by the flags.
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("key_2"),
+                OpenFlags::empty(),
+                Rights::FD_READ | Rights::FD_FILESTAT_SET_SIZE,
+                Rights::empty(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        assert_eq!(
+            bcfs.fdstat(ptx, fd).unwrap().rights_base,
+            Rights::FD_READ | Rights::FD_FILESTAT_SET_SIZE
+        );
+        assert_eq!(
+            bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"1")]),
+            Err(ErrNo::NotCapable)
+        );
+        assert_eq!(bcfs.set_size(ptx, fd, 0), Err(ErrNo::NotCapable));
+        assert_eq!(
+            bcfs.open(
+                ptx,
+                home,
+                Path::new("key_2"),
+                OpenFlags::TRUNC,
+                Rights::FD_READ,
+                Rights::empty(),
+                FdFlags::empty()
+            ),
+            Err(ErrNo::NotCapable)
+        );
+        assert_eq!(
+            bcfs.open(
+                ptx,
+                home,
+                Path::new("key_2"),
+                OpenFlags::empty(),
+                Rights::FD_READ | Rights::FD_WRITE,
+                Rights::empty(),
+                FdFlags::SYNC
+            ),
+            Err(ErrNo::NotCapable)
+        );
+
+        // A file created without `FD_WRITE` is empty and can be closed.
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("readonly_file"),
+                OpenFlags::CREATE,
+                Rights::FD_READ,
+                Rights::empty(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        assert_eq!(
+            bcfs.read_vectored(ptx, fd, &mut [IoSliceMut::new(&mut read_buf)]),
+            Ok(0)
+        );
+        assert_eq!(bcfs.close(ptx, fd), Ok(()));
+        assert_eq!(read_file(&mut bcfs, ptx, "readonly_file"), b"");
+
+        // Changes are written back only by fds that can write. The others are discarded.
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("key_2"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"1")]).unwrap();
+        bcfs.set_rights(ptx, fd, file_rights - Rights::FD_WRITE, Rights::empty())
+            .unwrap();
+        assert_eq!(bcfs.flush(ptx, fd), Ok(()));
+        assert_eq!(read_file(&mut bcfs, ptx, "key_2"), b"value_2");
+        assert_eq!(bcfs.close(ptx, fd), Ok(()));
+
+        // Files opened through a directory have at most its inheriting rights.
+        let dir_rights = bcfs.fdstat(ptx, home).unwrap();
+        assert_eq!(
+            bcfs.set_rights(
+                ptx,
+                home,
+                dir_rights.rights_base - Rights::PATH_CREATE_FILE,
+                dir_rights.rights_inheriting - Rights::FD_WRITE
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            bcfs.open(
+                ptx,
+                home,
+                Path::new("newfile"),
+                OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty()
+            ),
+            Err(ErrNo::NotCapable)
+        );
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("key_2"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        assert_eq!(
+            bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"1")]),
+            Err(ErrNo::NotCapable)
+        );
+        assert_eq!(
+            bcfs.read_vectored(ptx, fd, &mut [IoSliceMut::new(&mut read_buf)]),
+            Ok(1)
+        );
+
+        assert_eq!(bcfs.commit(ptx), Ok(()));
+        assert!(ptx.state().contains(b"readonly_file"));
+    }
+);
+
+testcase!(
+    fn quota(ptx: &mut dyn PendingTransaction) {
+        let home = HOME_DIR_FILENO.into();
+        let usage = BCFS::new(*ptx.address(), CHAIN_NAME).usage(ptx);
+        assert_eq!(usage.keys, 2);
+        assert_eq!(
+            usage.bytes,
+            ("common_key".len() + "common_value".len() + "key_2".len() + "value_2".len()) as u64
+        );
+
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME).with_quota(Quota {
+            max_bytes: Some(usage.bytes + 100),
+            max_keys: Some(usage.keys + 2), // a file and its metadata
+        });
+
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("file"),
+                OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        assert_eq!(bcfs.usage(ptx).keys, usage.keys + 2);
+        assert_eq!(
+            bcfs.open(
+                ptx,
+                home,
+                Path::new("another_file"),
+                OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty()
+            ),
+            Err(ErrNo::DQuot)
+        );
+        assert!(!ptx.state().contains(b"another_file"));
+        assert_eq!(
+            bcfs.create_directory(ptx, home, Path::new("dir")),
+            Err(ErrNo::DQuot)
+        );
+
+        assert_eq!(
+            bcfs.write_vectored(ptx, fd, &[IoSlice::new(&[0u8; 100])]),
+            Err(ErrNo::DQuot)
+        );
+        assert_eq!(
+            bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"hello")]),
+            Ok(5)
+        );
+        bcfs.close(ptx, fd).unwrap();
+        assert_eq!(read_file(&mut bcfs, ptx, "file"), b"hello");
+
+        // Files can still be rewritten when the quota is exhausted.
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("file"),
+                OpenFlags::TRUNC,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        assert_eq!(bcfs.close(ptx, fd), Ok(()));
+        assert_eq!(read_file(&mut bcfs, ptx, "file"), b"");
+
+        // The usage is kept up to date as the storage is modified.
+        let fresh_usage =
+            |ptx: &dyn PendingTransaction| BCFS::new(*ptx.address(), CHAIN_NAME).usage(ptx);
+        bcfs.commit(ptx).unwrap();
+        assert_eq!(bcfs.usage(ptx), fresh_usage(ptx));
+        bcfs.unlink(ptx, home, Path::new("file")).unwrap();
+        bcfs.commit(ptx).unwrap();
+        assert_eq!(bcfs.usage(ptx), fresh_usage(ptx));
+        assert_eq!(bcfs.usage(ptx), usage);
+    }
+);
+
+testcase!(
+    fn chunked_files(ptx: &mut dyn PendingTransaction) {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME).with_chunk_size(4);
+        let home = HOME_DIR_FILENO.into();
+        let num_chunks = |ptx: &dyn PendingTransaction, path: &str| {
+            let mut prefix = b"\0chunks/".to_vec();
+            prefix.extend_from_slice(path.as_bytes());
+            prefix.push(0);
+            ptx.state().scan_prefix(&prefix).len()
+        };
+
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("big"),
+                OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        assert_eq!(
+            bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"hello "), IoSlice::new(b"world!")]),
+            Ok(12)
+        );
+        bcfs.close(ptx, fd).unwrap();
+        bcfs.commit(ptx).unwrap();
+        assert_eq!(ptx.state().get(b"big"), Some(Vec::new()));
+        assert_eq!(num_chunks(ptx, "big"), 3);
+        assert_eq!(read_file(&mut bcfs, ptx, "big"), b"hello world!");
+
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("big"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        assert_eq!(bcfs.seek(ptx, fd, 6, Whence::Start), Ok(6));
+        assert_eq!(bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"W")]), Ok(1));
+        assert_eq!(bcfs.seek(ptx, fd, -2, Whence::End), Ok(10));
+        let mut read_buf = vec![0u8; 4];
+        assert_eq!(
+            bcfs.read_vectored(ptx, fd, &mut [IoSliceMut::new(&mut read_buf)]),
+            Ok(2)
+        );
+        assert_eq!(&read_buf[..2], b"d!");
+        assert_eq!(bcfs.set_size(ptx, fd, 7), Ok(()));
+        assert_eq!(bcfs.filestat(ptx, fd).unwrap().file_size, 7);
+        assert_eq!(
+            bcfs.pwrite_vectored(ptx, fd, &[IoSlice::new(b"abcd")], u64::max_value() - 1),
+            Err(ErrNo::FBig)
+        );
+        bcfs.close(ptx, fd).unwrap();
+        bcfs.commit(ptx).unwrap();
+        assert_eq!(read_file(&mut bcfs, ptx, "big"), b"hello W");
+        assert_eq!(num_chunks(ptx, "big"), 2);
+
+        // Truncating and extending a file fills it with zeros, which are not stored.
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("big"),
+                OpenFlags::TRUNC,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        assert_eq!(bcfs.set_size(ptx, fd, 6), Ok(()));
+        assert_eq!(bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"ab")]), Ok(2));
+        bcfs.close(ptx, fd).unwrap();
+        assert_eq!(read_file(&mut bcfs, ptx, "big"), b"ab\0\0\0\0");
+
+        assert_eq!(
+            bcfs.rename(ptx, home, Path::new("big"), home, Path::new("moved")),
+            Ok(())
+        );
+        bcfs.commit(ptx).unwrap();
+        assert_eq!(num_chunks(ptx, "big"), 0);
+        assert_eq!(num_chunks(ptx, "moved"), 1);
+        assert_eq!(bcfs.unlink(ptx, home, Path::new("moved")), Ok(6));
+        bcfs.commit(ptx).unwrap();
+        assert!(ptx.state().scan_prefix(b"\0chunks/").is_empty());
+
+        // Existing files are not chunked.
+        let fd = bcfs
+            .open(
+                ptx,
+                home,
+                Path::new("key_2"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::APPEND,
+            )
+            .unwrap();
+        bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"!")]).unwrap();
+        bcfs.close(ptx, fd).unwrap();
+        bcfs.commit(ptx).unwrap();
+        assert_eq!(ptx.state().get(b"key_2"), Some(b"value_2!".to_vec()));
+        assert_eq!(num_chunks(ptx, "key_2"), 0);
+    }
+);
+
+testcase!(
+    fn events_file(ptx: &mut dyn PendingTransaction) {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
+
+        fn open(bcfs: &mut BCFS, ptx: &mut dyn PendingTransaction, path: &str) -> Result<Fd> {
+            bcfs.open(
+                ptx,
+                CHAIN_DIR_FILENO.into(),
+                Path::new(path),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::APPEND,
+            )
+        }
+
+        fn read_events(bcfs: &mut BCFS, ptx: &mut dyn PendingTransaction) -> Vec<u8> {
+            let fd = bcfs
+                .open(
+                    ptx,
+                    CHAIN_DIR_FILENO.into(),
+                    Path::new("events"),
+                    OpenFlags::empty(),
+                    Rights::all(),
+                    Rights::all(),
+                    FdFlags::empty(),
+                )
+                .unwrap();
+            let mut buf = vec![0u8; 1024];
+            let nbytes = bcfs
+                .read_vectored(ptx, fd, &mut [IoSliceMut::new(&mut buf)])
+                .unwrap();
+            bcfs.close(ptx, fd).unwrap();
+            buf.truncate(nbytes);
+            buf
+        }
+
+        assert_eq!(read_events(&mut bcfs, ptx), Vec::<u8>::new());
+
+        let (topics, data, log) = create_log();
+        let log_fd = open(&mut bcfs, ptx, "log").unwrap();
+        for _ in 0..2 {
+            bcfs.write_vectored(ptx, log_fd, &[IoSlice::new(&log)])
+                .unwrap();
+            bcfs.flush(ptx, log_fd).unwrap();
+        }
+        bcfs.flush(ptx, log_fd).unwrap(); // Nothing was written, so nothing is emitted.
+
+        let mut event = (topics.len() as u32).to_le_bytes().to_vec();
+        for topic in topics.iter() {
+            let mut padded_topic = [0u8; 32];
+            padded_topic[..topic.len()].copy_from_slice(topic);
+            event.extend_from_slice(&32u32.to_le_bytes());
+            event.extend_from_slice(&padded_topic);
+        }
+        event.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        event.extend_from_slice(&data);
+        assert_eq!(read_events(&mut bcfs, ptx), [event.clone(), event].concat());
+
+        // Malformed events are rejected and discarded.
+        bcfs.write_vectored(ptx, log_fd, &[IoSlice::new(&log[1..])])
+            .unwrap();
+        assert_eq!(bcfs.flush(ptx, log_fd), Err(ErrNo::Inval));
+        bcfs.write_vectored(
+            ptx,
+            log_fd,
+            &[IoSlice::new(&[log.as_slice(), &b"!"[..]].concat())],
+        )
+        .unwrap();
+        assert_eq!(bcfs.close(ptx, log_fd), Err(ErrNo::Inval));
+        assert_eq!(ptx.events().len(), 2);
+        assert!(bcfs.close(ptx, log_fd).is_ok());
+
+        // The events file is read-only.
+        let events_fd = open(&mut bcfs, ptx, "events").unwrap();
+        assert_eq!(
+            bcfs.write_vectored(ptx, events_fd, &[IoSlice::new(b"event")]),
+            Err(ErrNo::NotCapable)
+        );
+        assert_eq!(
+            bcfs.open(
+                ptx,
+                CHAIN_DIR_FILENO.into(),
+                Path::new("events"),
+                OpenFlags::DIRECTORY,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            ),
+            Err(ErrNo::NotDir)
+        );
+    }
+);
+
+#[test]
+fn events_file_call_tree() {
+    fn read_events(ptx: &mut dyn PendingTransaction) -> Vec<u8> {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
+        let fd = bcfs
+            .open(
+                ptx,
+                CHAIN_DIR_FILENO.into(),
+                Path::new("events"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        let mut buf = vec![0u8; 1024];
+        let nbytes = bcfs
+            .read_vectored(ptx, fd, &mut [IoSliceMut::new(&mut buf)])
+            .unwrap();
+        buf.truncate(nbytes);
+        buf
+    }
+
+    fn encode_event(topic: &[u8; 32], data: &[u8]) -> Vec<u8> {
+        let mut event = 1u32.to_le_bytes().to_vec();
+        event.extend_from_slice(&32u32.to_le_bytes());
+        event.extend_from_slice(topic);
+        event.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        event.extend_from_slice(data);
+        event
+    }
+
+    fn callee_main(ptx: &mut dyn PendingTransaction) -> u16 {
+        // The callee sees the events that its caller emitted before calling it.
+        assert_eq!(read_events(ptx), encode_event(&[1u8; 32], b"caller"));
+        ptx.emit(&[&[2u8; 32][..]], b"callee");
+        assert_eq!(
+            read_events(ptx),
+            [
+                encode_event(&[1u8; 32], b"caller"),
+                encode_event(&[2u8; 32], b"callee")
+            ]
+            .concat()
+        );
+        0
+    }
+
+    fn caller_main(ptx: &mut dyn PendingTransaction) -> u16 {
+        ptx.emit(&[&[1u8; 32][..]], b"caller");
+        assert!(!ptx.transact(ADDR_1, 0, b"").reverted());
+        assert_eq!(
+            read_events(ptx),
+            [
+                encode_event(&[1u8; 32], b"caller"),
+                encode_event(&[2u8; 32], b"callee")
+            ]
+            .concat()
+        );
+        0
+    }
+
+    let mut bc = create_memchain(vec![Some(callee_main), Some(caller_main)]);
+    let receipt = bc.last_block_mut().transact(
+        ADDR_1,
+        ADDR_2,
+        ADDR_1, /* payer */
+        0,      /* value */
+        b"",
+        BASE_GAS * 10,
+        GAS_PRICE,
+    );
+    assert_eq!(receipt.outcome(), TransactionOutcome::Success);
+    assert_eq!(receipt.events().len(), 2);
+}
+
+#[test]
+fn transact() {
+    fn callee_main(ptx: &mut dyn PendingTransaction) -> u16 {
+        if ptx.input() == b"fail" {
+            ptx.err(b"failed");
+            return 1;
+        }
+        let output = ptx.input().to_vec();
+        ptx.ret(&output);
+        0
+    }
+
+    fn test_main(ptx: &mut dyn PendingTransaction) -> u16 {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
+
+        fn call(
+            bcfs: &mut BCFS,
+            ptx: &mut dyn PendingTransaction,
+            callee: Address,
+            value: u128,
+            input: &[u8],
+        ) -> (Vec<u8>, Result<()>) {
+            let (fd, result) = bcfs.transact(ptx, callee, value, input).unwrap();
+            assert_eq!(
+                bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"output")]),
+                Err(ErrNo::NotCapable)
+            );
+            let mut buf = vec![0u8; 64];
+            let nbytes = bcfs
+                .read_vectored(ptx, fd, &mut [IoSliceMut::new(&mut buf)])
+                .unwrap();
+            bcfs.close(ptx, fd).unwrap();
+            buf.truncate(nbytes);
+            (buf, result)
+        }
+
+        assert_eq!(
+            call(&mut bcfs, ptx, ADDR_1, 1, b"hello"),
+            (b"hello".to_vec(), Ok(()))
+        );
+        assert_eq!(
+            call(&mut bcfs, ptx, ADDR_1, 0, b"fail"),
+            (b"failed".to_vec(), Err(ErrNo::ConnAborted))
+        );
+        assert_eq!(
+            call(&mut bcfs, ptx, Address([3u8; 20]), 0, b""),
+            (Vec::new(), Err(ErrNo::NoEnt))
+        );
+        assert_eq!(
+            call(&mut bcfs, ptx, ADDR_1, giga(100), b""),
+            (Vec::new(), Err(ErrNo::DQuot))
+        );
+        0
+    }
+
+    let mut bc = create_memchain(vec![Some(callee_main), Some(test_main)]);
+    let receipt = bc.last_block_mut().transact(
+        ADDR_1,
+        ADDR_2,
+        ADDR_1, /* payer */
+        0,      /* value */
+        b"input",
+        BASE_GAS * 10,
+        GAS_PRICE,
+    );
+    assert_eq!(receipt.outcome(), TransactionOutcome::Success);
+}
+
+#[test]
+fn create() {
+    fn test_main(ptx: &mut dyn PendingTransaction) -> u16 {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
+
+        let (fd, result) = bcfs.create(ptx, 100, b"\0asm new service").unwrap();
+        assert_eq!(result, Ok(()));
+        let mut buf = vec![0u8; 64];
+        let nbytes = bcfs
+            .read_vectored(ptx, fd, &mut [IoSliceMut::new(&mut buf)])
+            .unwrap();
+        assert_eq!(nbytes, Address::size());
+        let mut addr = Address::default();
+        addr.0.copy_from_slice(&buf[..nbytes]);
+        assert_eq!(ptx.code_at(&addr), Some(&b"\0asm new service"[..]));
+        assert_eq!(ptx.account_meta_at(&addr).unwrap().balance, 100);
+        bcfs.close(ptx, fd).unwrap();
+
+        let (fd, result) = bcfs.create(ptx, giga(100), b"\0asm new service").unwrap();
+        assert_eq!(result, Err(ErrNo::DQuot));
+        bcfs.close(ptx, fd).unwrap();
+        0
+    }
+
+    let mut bc = create_memchain(vec![None, Some(test_main)]);
+    let receipt = bc.last_block_mut().transact(
+        ADDR_1,
+        ADDR_2,
+        ADDR_1, /* payer */
+        0,      /* value */
+        b"input",
+        BASE_GAS * 10,
+        GAS_PRICE,
+    );
+    assert_eq!(receipt.outcome(), TransactionOutcome::Success);
+}
+
+#[test]
+fn environ() {
+    fn test_main(ptx: &mut dyn PendingTransaction) -> u16 {
+        let environ = Environ::new(ptx);
+        let vars: Vec<&[u8]> = environ
+            .offsets()
+            .iter()
+            .map(|offset| {
+                let var = &environ.buf()[*offset..];
+                &var[..var.iter().position(|b| *b == 0).unwrap()]
+            })
+            .collect();
+        assert_eq!(
+            vars,
+            vec![
+                format!("ADDRESS={}", hex::encode(&ADDR_2)).as_bytes(),
+                format!("SENDER={}", hex::encode(&ADDR_1)).as_bytes(),
+                format!("PAYER={}", hex::encode(&ADDR_3)).as_bytes(),
+                &b"VALUE=42"[..],
+                &b"AAD="[..],
+            ]
+        );
+        assert_eq!(environ.sizes(), (5, environ.buf().len()));
+        assert_eq!(environ.buf().last(), Some(&0));
+        0
+    }
+
+    let mut bc = create_memchain(vec![None, Some(test_main), None]);
+    let receipt = bc.last_block_mut().transact(
+        ADDR_1, ADDR_2, ADDR_3, /* payer */
+        42,     /* value */
+        b"input", BASE_GAS, GAS_PRICE,
+    );
+    assert_eq!(receipt.outcome(), TransactionOutcome::Success);
+}
+
+#[test]
+fn clock_and_random() {
+    fn test_main(ptx: &mut dyn PendingTransaction) -> u16 {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
+        for clock_id in vec![ClockId::RealTime, ClockId::Monotonic] {
+            assert_eq!(
+                bcfs.clock_time_get(ptx, clock_id, Timestamp::from(1u64)),
+                Ok(Timestamp::from(ptx.block_timestamp()))
+            );
+        }
+
+        let mut random = [0u8; 100];
+        bcfs.random_get(ptx, &mut random[..10]).unwrap();
+        bcfs.random_get(ptx, &mut random[10..]).unwrap();
+
+        let mut random_2 = [0u8; 100];
+        BCFS::new(*ptx.address(), CHAIN_NAME)
+            .random_get(ptx, &mut random_2)
+            .unwrap();
+        assert_eq!(random[..], random_2[..]);
+
+        ptx.ret(&random);
+        0
+    }
+
+    fn transact(block: &mut dyn Block, input: &[u8]) -> Vec<u8> {
+        let receipt = block.transact(
+            ADDR_1, ADDR_2, ADDR_1, /* payer */
+            0,      /* value */
+            input, BASE_GAS, GAS_PRICE,
+        );
+        assert_eq!(receipt.outcome(), TransactionOutcome::Success);
+        receipt.output().to_vec()
+    }
+
+    let mut bc = create_memchain(vec![None, Some(test_main)]);
+    let output = transact(bc.create_block_with_timestamp(1000), b"input");
+    assert_eq!(output.len(), 100);
+    // The sender's nonce distinguishes identical transactions in the same block.
+    assert_ne!(transact(bc.last_block_mut(), b"input"), output);
+    assert_ne!(transact(bc.last_block_mut(), b"other"), output);
+    assert_ne!(
+        transact(bc.create_block_with_timestamp(2000), b"input"),
+        output
+    );
+}
+
+testcase!(
+    fn account_files(ptx: &mut dyn PendingTransaction) {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
+
+        fn read_account_file(
+            bcfs: &mut BCFS,
+            ptx: &mut dyn PendingTransaction,
+            addr: Address,
+            name: &str,
+        ) -> Result<Vec<u8>> {
+            let fd = bcfs.open(
+                ptx,
+                CHAIN_DIR_FILENO.into(),
+                &Path::new(&hex::encode(&addr)).join(name),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )?;
+            let mut buf = vec![0u8; 64];
+            let nbytes = bcfs.read_vectored(ptx, fd, &mut [IoSliceMut::new(&mut buf)]);
+            bcfs.close(ptx, fd).unwrap();
+            buf.truncate(nbytes?);
+            Ok(buf)
+        }
+
+        assert_eq!(
+            read_account_file(&mut bcfs, ptx, ADDR_1, ".expiry"),
+            Ok(Vec::new())
+        );
+        assert_eq!(
+            read_account_file(&mut bcfs, ptx, ADDR_1, ".code_hash"),
+            Ok(tiny_keccak::keccak256(ptx.code_at(&ADDR_1).unwrap()).to_vec())
+        );
+        assert_eq!(
+            read_account_file(&mut bcfs, ptx, ADDR_1, ".nonce"),
+            Ok(1u64.to_le_bytes().to_vec())
+        );
+
+        // Each account has exactly one directory: its address in lowercase hex.
+        let addr_hex = hex::encode(&ADDR_1);
+        for addr_dir in &[format!("0x{}", addr_hex), addr_hex.to_uppercase()] {
+            assert_eq!(
+                bcfs.open(
+                    ptx,
+                    CHAIN_DIR_FILENO.into(),
+                    &Path::new(addr_dir).join("balance"),
+                    OpenFlags::empty(),
+                    Rights::all(),
+                    Rights::all(),
+                    FdFlags::empty(),
+                ),
+                Err(ErrNo::NoEnt)
+            );
+        }
+        assert_eq!(
+            read_account_file(&mut bcfs, ptx, ADDR_2, ".nonce"),
+            Ok(0u64.to_le_bytes().to_vec())
+        );
+        assert_eq!(
+            read_account_file(&mut bcfs, ptx, ADDR_1, ".interface"),
+            Err(ErrNo::NoEnt)
+        );
+        assert_eq!(
+            read_account_file(&mut bcfs, ptx, ADDR_3, ".nonce"),
+            Err(ErrNo::NoEnt)
+        );
+
+        assert_eq!(
+            bcfs.open(
+                ptx,
+                HOME_DIR_FILENO.into(),
+                Path::new(".nonce"),
+                OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            ),
+            Err(ErrNo::Exist)
+        );
+        assert_eq!(
+            bcfs.open(
+                ptx,
+                HOME_DIR_FILENO.into(),
+                Path::new(".interface"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::APPEND,
+            )
+            .and_then(|fd| bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"iface")])),
+            Err(ErrNo::NotCapable)
+        );
+
+        // Undotted names are free for the account's own files.
+        let fd = bcfs
+            .open(
+                ptx,
+                HOME_DIR_FILENO.into(),
+                Path::new("nonce"),
+                OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"mine")])
+            .unwrap();
+        bcfs.close(ptx, fd).unwrap();
+        assert_eq!(read_file(&mut bcfs, ptx, "nonce"), b"mine");
+        assert_eq!(
+            bcfs.unlink(ptx, HOME_DIR_FILENO.into(), Path::new("nonce")),
+            Ok(4)
+        );
+    }
+);
+
+testcase!(
+    fn commit_and_abort(ptx: &mut dyn PendingTransaction) {
+        fn open(bcfs: &mut BCFS, ptx: &mut dyn PendingTransaction, path: &str) -> Fd {
+            bcfs.open(
+                ptx,
+                HOME_DIR_FILENO.into(),
+                Path::new(path),
+                OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap()
+        }
+
+        fn write(bcfs: &mut BCFS, ptx: &mut dyn PendingTransaction, fd: Fd, buf: &[u8]) {
+            bcfs.write_vectored(ptx, fd, &[IoSlice::new(buf)]).unwrap();
+        }
+
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME).with_staged_writes();
+        let file_fd = open(&mut bcfs, ptx, "file");
+        let key_fd = open(&mut bcfs, ptx, "key_2");
+        let log_fd = bcfs
+            .open(
+                ptx,
+                CHAIN_DIR_FILENO.into(),
+                Path::new("log"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::APPEND,
+            )
+            .unwrap();
+        write(&mut bcfs, ptx, file_fd, b"abc");
+        write(&mut bcfs, ptx, key_fd, b"V");
+        write(&mut bcfs, ptx, log_fd, &create_log().2);
+        assert!(!ptx.state().contains(b"file"));
+
+        for _ in 0..2 {
+            bcfs.commit(ptx).unwrap();
+            assert_eq!(ptx.state().get(b"file"), Some(b"abc".to_vec()));
+            assert_eq!(ptx.state().get(b"key_2"), Some(b"Value_2".to_vec()));
+            assert_eq!(ptx.events().len(), 1);
+        }
+
+        write(&mut bcfs, ptx, file_fd, b"def");
+        write(&mut bcfs, ptx, log_fd, &create_log().2);
+        bcfs.abort(ptx);
+        bcfs.commit(ptx).unwrap();
+        assert_eq!(ptx.state().get(b"file"), Some(b"abc".to_vec()));
+        assert_eq!(ptx.events().len(), 1);
+        let mut buf = vec![0u8; 8];
+        bcfs.seek(ptx, file_fd, 0, Whence::Start).unwrap();
+        let nbytes = bcfs
+            .read_vectored(ptx, file_fd, &mut [IoSliceMut::new(&mut buf)])
+            .unwrap();
+        assert_eq!(&buf[..nbytes], b"abc");
+
+        // A commit that fails writes nothing.
+        write(&mut bcfs, ptx, file_fd, b"ghi");
+        write(&mut bcfs, ptx, log_fd, b"not an event");
+        assert_eq!(bcfs.commit(ptx), Err(ErrNo::Inval));
+        assert_eq!(ptx.state().get(b"file"), Some(b"abc".to_vec()));
+        bcfs.abort(ptx);
+
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME).with_staged_writes();
+        let a_fd = open(&mut bcfs, ptx, "a");
+        let b_fd = open(&mut bcfs, ptx, "b");
+        let max_bytes = bcfs.usage(ptx).bytes + 15;
+        let mut bcfs = bcfs.with_quota(Quota {
+            max_bytes: Some(max_bytes),
+            max_keys: None,
+        });
+        write(&mut bcfs, ptx, a_fd, &[0u8; 10]);
+        write(&mut bcfs, ptx, b_fd, &[0u8; 10]);
+        assert_eq!(bcfs.commit(ptx), Err(ErrNo::DQuot));
+        assert!(!ptx.state().contains(b"a"));
+        assert!(!ptx.state().contains(b"b"));
+    }
+);
+
+#[test]
+fn commit_hides_changes_from_calls() {
+    fn test_main(ptx: &mut dyn PendingTransaction) -> u16 {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME).with_staged_writes();
+
+        if ptx.input() == b"reenter" {
+            let opened = bcfs.open(
+                ptx,
+                HOME_DIR_FILENO.into(),
+                Path::new("file"),
+                OpenFlags::empty(),
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            );
+            let output = match opened {
+                Ok(_) => read_file(&mut bcfs, ptx, "file"),
+                Err(errno) => {
+                    assert_eq!(errno, ErrNo::NoEnt);
+                    b"no file".to_vec()
+                }
+            };
+            ptx.ret(&output);
+            return 0;
+        }
+
+        fn reenter(ptx: &mut dyn PendingTransaction) -> Vec<u8> {
+            let receipt = ptx.transact(*ptx.address(), 0, b"reenter");
+            assert_eq!(receipt.outcome(), TransactionOutcome::Success);
+            receipt.output().to_vec()
+        }
+
+        let fd = bcfs
+            .open(
+                ptx,
+                HOME_DIR_FILENO.into(),
+                Path::new("file"),
+                OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"abc")])
+            .unwrap();
+        bcfs.flush(ptx, fd).unwrap();
+        assert_eq!(read_file(&mut bcfs, ptx, "file"), b"abc");
+        assert_eq!(reenter(ptx), b"no file");
+
+        bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"def")])
+            .unwrap();
+        bcfs.close(ptx, fd).unwrap();
+        assert_eq!(reenter(ptx), b"no file");
+
+        bcfs.commit(ptx).unwrap();
+        assert_eq!(reenter(ptx), b"abcdef");
+        0
+    }
+
+    let mut bc = create_memchain(vec![None, Some(test_main)]);
+    let receipt = bc.last_block_mut().transact(
+        ADDR_1,
+        ADDR_2,
+        ADDR_1, /* payer */
+        0,      /* value */
+        b"",
+        BASE_GAS * 10,
+        GAS_PRICE,
+    );
+    assert_eq!(receipt.outcome(), TransactionOutcome::Success);
+}
+
+testcase!(
+    fn write_through(ptx: &mut dyn PendingTransaction) {
+        let mut bcfs = BCFS::new(*ptx.address(), CHAIN_NAME);
+        let fd = bcfs
+            .open(
+                ptx,
+                HOME_DIR_FILENO.into(),
+                Path::new("file"),
+                OpenFlags::CREATE,
+                Rights::all(),
+                Rights::all(),
+                FdFlags::empty(),
+            )
+            .unwrap();
+        assert_eq!(ptx.state().get(b"file"), Some(Vec::new()));
+        bcfs.write_vectored(ptx, fd, &[IoSlice::new(b"abc")])
+            .unwrap();
+        assert_eq!(ptx.state().get(b"file"), Some(Vec::new()));
+        bcfs.flush(ptx, fd).unwrap();
+        assert_eq!(ptx.state().get(b"file"), Some(b"abc".to_vec()));
+
+        // Changes that were written are left for the transaction to revert.
+        bcfs.abort(ptx);
+        assert_eq!(read_file(&mut bcfs, ptx, "file"), b"abc");
+        bcfs.unlink(ptx, HOME_DIR_FILENO.into(), Path::new("file"))
+            .unwrap();
+        assert!(!ptx.state().contains(b"file"));
+    }
+);
@@ -0,0 +1,71 @@
+//! Just enough of the Wasm binary format to find custom sections in account bytecode.
+
+use std::convert::TryFrom as _;
+
+const MAGIC: &[u8] = b"\0asm";
+const CUSTOM_SECTION_ID: u8 = 0;
+
+/// Returns the contents of the first custom section named `name` in `bytecode`, or `None`
+/// if there is no such section or `bytecode` is not a Wasm module.
+pub fn custom_section<'a>(bytecode: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    if bytecode.len() < 8 || &bytecode[..4] != MAGIC {
+        return None;
+    }
+    let mut rest = &bytecode[8..]; // skip the magic and the version
+    while !rest.is_empty() {
+        let id = rest[0];
+        let (section_len, section_start) = read_leb128(&rest[1..])?;
+        let section_end = section_start.checked_add(section_len)?;
+        let section = rest[1..].get(section_start..section_end)?;
+        if id == CUSTOM_SECTION_ID {
+            let (name_len, name_start) = read_leb128(section)?;
+            let name_end = name_start.checked_add(name_len)?;
+            if section.get(name_start..name_end)? == name.as_bytes() {
+                return Some(&section[name_end..]);
+            }
+        }
+        rest = &rest[(1 + section_end)..];
+    }
+    None
+}
+
+/// Reads an unsigned LEB128-encoded u32 from the start of `buf`.
+/// Returns the value and the number of bytes that it occupies.
+fn read_leb128(buf: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0u32;
+    for (i, b) in buf.iter().enumerate().take(5) {
+        value |= u32::from(b & 0x7f).checked_shl(7 * i as u32)?;
+        if b & 0x80 == 0 {
+            return Some((usize::try_from(value).ok()?, i + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_section() {
+        let mut bytecode = b"\0asm\x01\0\0\0".to_vec();
+        bytecode.extend_from_slice(&[1, 4, 1, 0x60, 0, 0]); // a type section
+        bytecode.extend_from_slice(&[0, 5, 3, b'a', b'b', b'c', 42]);
+        bytecode.extend_from_slice(&[0, 0x85, 0, 3, b'x', b'y', b'z', 43]); // padded LEB128
+
+        assert_eq!(custom_section(&bytecode, "abc"), Some(&[42u8][..]));
+        assert_eq!(custom_section(&bytecode, "xyz"), Some(&[43u8][..]));
+        assert_eq!(custom_section(&bytecode, "ab"), None);
+        assert_eq!(
+            custom_section(&bytecode[..(bytecode.len() - 1)], "xyz"),
+            None
+        );
+        assert_eq!(custom_section(b"\0asm not wasm", "abc"), None);
+    }
+
+    quickcheck::quickcheck! {
+        fn custom_section_nopanic(inp: Vec<u8>) -> () {
+            custom_section(&[b"\0asm\x01\0\0\0".to_vec(), inp].concat(), "abc");
+        }
+    }
+}
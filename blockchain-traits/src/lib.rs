@@ -1,4 +1,4 @@
-use oasis_types::{AccountMeta, Address, Event};
+use oasis_types::{AccountMeta, Address, ErrorCode, Event};
 
 pub trait Blockchain {
     /// Returns the name of this blockchain.
@@ -60,12 +60,26 @@ pub trait PendingTransaction {
     /// Returns the address of the sender of the transaction.
     fn sender(&self) -> &Address;
 
+    /// Returns the address of the account that pays for the gas of the transaction.
+    fn payer(&self) -> &Address;
+
     /// Returns the value sent to the current transaction.
     fn value(&self) -> u128;
 
     /// Returns the input provided by the calling context.
     fn input(&self) -> &[u8];
 
+    /// Returns the additional authenticated data provided with the input of a confidential
+    /// transaction. Empty if the transaction is not confidential.
+    fn aad(&self) -> &[u8];
+
+    /// Returns the height of the block in which this transaction is executing.
+    fn block_height(&self) -> u64;
+
+    /// Returns the timestamp of the block in which this transaction is executing,
+    /// in nanoseconds since the Unix epoch.
+    fn block_timestamp(&self) -> u64;
+
     /// Creates a new contract with the provided code and initial value.
     /// The new transaction will inherit the gas parameters and gas payer of the top level
     /// transaction. The current account will be set as the sender.
@@ -76,6 +90,20 @@ pub trait PendingTransaction {
     /// transaction. The current account will be set as the sender.
     fn transact(&mut self, callee: Address, value: u128, input: &[u8]) -> Box<dyn Receipt>;
 
+    /// Schedules a balance-transferring RPC to `callee` that will be executed at the start
+    /// of the block at `height`, which must be in the future.
+    /// The current account will be set as the sender and is charged for the `value` and
+    /// the scheduled call's `gas` when the call is scheduled.
+    /// Returns `TransactionOutcome::Success` if the call was scheduled.
+    fn schedule(
+        &mut self,
+        height: u64,
+        callee: Address,
+        value: u128,
+        input: &[u8],
+        gas: u64,
+    ) -> TransactionOutcome;
+
     /// Returns data to the calling transaction.
     fn ret(&mut self, data: &[u8]);
 
@@ -85,6 +113,11 @@ pub trait PendingTransaction {
     /// Publishes a broadcast message in this block.
     fn emit(&mut self, topics: &[&[u8]], data: &[u8]);
 
+    /// Returns the events emitted so far in the call tree of this transaction: those emitted by
+    /// its callers before calling it, followed by those emitted by this transaction and the
+    /// transactions it called.
+    fn events(&self) -> Vec<&Event>;
+
     /// Returns the state of the current account.
     fn state(&self) -> &dyn KVStore;
 
@@ -106,6 +139,9 @@ pub trait KVStore {
 
     /// Returns the data stored in the account at `addr` under the given `key`.
     fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
+
+    /// Returns the keys in account storage that start with `prefix`, in lexicographic order.
+    fn scan_prefix(&self, prefix: &[u8]) -> Vec<Vec<u8>>;
 }
 
 pub trait KVStoreMut: KVStore {
@@ -164,3 +200,35 @@ impl TransactionOutcome {
         }
     }
 }
+
+impl From<TransactionOutcome> for ErrorCode {
+    fn from(outcome: TransactionOutcome) -> Self {
+        match outcome {
+            TransactionOutcome::Success => ErrorCode::Success,
+            TransactionOutcome::InsufficientFunds => ErrorCode::InsufficientFunds,
+            TransactionOutcome::InsufficientGas => ErrorCode::InsufficientGas,
+            TransactionOutcome::InvalidInput => ErrorCode::InvalidInput,
+            TransactionOutcome::InvalidCallee => ErrorCode::InvalidCallee,
+            TransactionOutcome::Aborted => ErrorCode::Execution,
+            TransactionOutcome::Fatal => ErrorCode::Fatal,
+        }
+    }
+}
+
+/// Fails with the `ErrorCode` if it is not the outcome of a transaction.
+impl std::convert::TryFrom<ErrorCode> for TransactionOutcome {
+    type Error = ErrorCode;
+
+    fn try_from(code: ErrorCode) -> Result<Self, Self::Error> {
+        Ok(match code {
+            ErrorCode::Success => TransactionOutcome::Success,
+            ErrorCode::InsufficientFunds => TransactionOutcome::InsufficientFunds,
+            ErrorCode::InsufficientGas => TransactionOutcome::InsufficientGas,
+            ErrorCode::InvalidInput => TransactionOutcome::InvalidInput,
+            ErrorCode::InvalidCallee => TransactionOutcome::InvalidCallee,
+            ErrorCode::Execution => TransactionOutcome::Aborted,
+            ErrorCode::Fatal => TransactionOutcome::Fatal,
+            _ => return Err(code),
+        })
+    }
+}
@@ -1,33 +1,129 @@
+use std::sync::Arc;
+
 use blockchain_traits::TransactionOutcome;
 use oasis_types::{AccountMeta, Address, Event};
 
-use crate::{output::Receipt, pending_transaction::PendingTransaction, State};
+use crate::{
+    confidential::CallInput,
+    journal::{Entry, Journal, Transaction},
+    output::Receipt,
+    pending_transaction::PendingTransaction,
+    schedule::ScheduledCall,
+    State,
+};
 
 #[derive(Debug, PartialEq, Eq)]
-pub struct Block<'bc> {
+pub struct Block {
     pub base_gas: u64,
+    pub storage_price: u128,
     pub height: u64,
-    pub state: State<'bc>,
+
+    /// The time at which the block was created, in nanoseconds since the Unix epoch.
+    pub timestamp: u64,
+
+    pub state: State,
     pub completed_transactions: Vec<Receipt>,
+
+    /// Calls that have been scheduled for execution in a future block.
+    pub scheduled_calls: Vec<ScheduledCall>,
+
+    /// The journal to which the transactions executed in this block are recorded, if any.
+    pub journal: Option<Journal>,
 }
 
-impl<'bc> Block<'bc> {
-    pub fn new(height: u64, state: State<'bc>, base_gas: u64) -> Self {
+impl Block {
+    pub fn new(height: u64, state: State, base_gas: u64) -> Self {
         Self {
             height,
+            timestamp: 0,
             state,
             completed_transactions: Vec::new(),
+            scheduled_calls: Vec::new(),
             base_gas,
+            storage_price: 0,
+            journal: None,
         }
     }
-}
 
-impl<'bc> blockchain_traits::Block for Block<'bc> {
-    fn height(&self) -> u64 {
-        self.height
+    /// Executes the scheduled calls that are due at or before the height of this block,
+    /// in the order in which they were scheduled.
+    pub(crate) fn execute_scheduled_calls(&mut self) {
+        let height = self.height;
+        let (due_calls, pending_calls) = std::mem::take(&mut self.scheduled_calls)
+            .into_iter()
+            .partition(|call: &ScheduledCall| call.height <= height);
+        self.scheduled_calls = pending_calls;
+        for call in due_calls {
+            let receipt = self.execute_scheduled_call(call);
+            self.completed_transactions.push(receipt);
+        }
     }
 
-    fn transact(
+    /// Executes a scheduled call. Like a top-level transaction, a call that runs is charged all
+    /// of its gas because memchain doesn't meter execution. A call that can't run because its
+    /// callee no longer exists or its input can't be opened is charged only `base_gas`. The
+    /// rest of the gas that was prepaid when the call was scheduled is refunded to the caller,
+    /// as is the value of any call that doesn't succeed.
+    fn execute_scheduled_call(&mut self, call: ScheduledCall) -> Receipt {
+        let mut receipt = Receipt {
+            caller: call.caller,
+            callee: call.callee,
+            value: call.value,
+            gas_used: call.gas,
+            output: Vec::new(),
+            events: Vec::new(),
+            outcome: TransactionOutcome::Success,
+        };
+
+        let call_input = self.state.get(&call.callee).map(|callee_acct| {
+            CallInput::open(
+                callee_acct.confidential_key.as_ref(),
+                &call.caller,
+                &call.input,
+            )
+        });
+        match call_input {
+            None => {
+                receipt.outcome = TransactionOutcome::InvalidCallee;
+                receipt.gas_used = std::cmp::min(self.base_gas, call.gas);
+            }
+            Some(None) => {
+                receipt.outcome = TransactionOutcome::InvalidInput;
+                receipt.gas_used = std::cmp::min(self.base_gas, call.gas);
+            }
+            Some(Some(call_input)) => {
+                // The value was already deducted from the caller when the call was scheduled.
+                let mut ptx_state = self.state.clone();
+                Arc::make_mut(ptx_state.get_mut(&call.callee).unwrap()).balance += call.value;
+                let gas_left = call.gas - self.base_gas;
+                receipt = self.run(
+                    receipt,
+                    call.caller, /* payer */
+                    ptx_state,
+                    call_input.input,
+                    call_input.aad,
+                    gas_left,
+                    call.gas_price,
+                );
+                if let Some(sealer) = call_input.sealer {
+                    sealer.seal_receipt(&mut receipt);
+                }
+            }
+        }
+
+        let mut refund = u128::from(call.gas - receipt.gas_used) * u128::from(call.gas_price);
+        if blockchain_traits::Receipt::reverted(&receipt) {
+            // Return the value that was reserved when the call was scheduled.
+            refund += call.value;
+        }
+        if let Some(caller_acct) = self.state.get_mut(&call.caller) {
+            Arc::make_mut(caller_acct).balance += refund;
+        }
+        receipt
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn execute(
         &mut self,
         caller: Address,
         callee: Address,
@@ -36,7 +132,7 @@ impl<'bc> blockchain_traits::Block for Block<'bc> {
         input: &[u8],
         gas: u64,
         gas_price: u64,
-    ) -> Box<dyn blockchain_traits::Receipt> {
+    ) -> Receipt {
         let mut receipt = Receipt {
             caller,
             callee,
@@ -50,8 +146,7 @@ impl<'bc> blockchain_traits::Block for Block<'bc> {
         macro_rules! early_return {
             ($outcome:ident) => {{
                 receipt.outcome = TransactionOutcome::$outcome;
-                self.completed_transactions.push(receipt.clone());
-                return box receipt;
+                return receipt;
             }};
         }
 
@@ -65,7 +160,7 @@ impl<'bc> blockchain_traits::Block for Block<'bc> {
 
         match self.state.get_mut(&payer) {
             Some(payer_acct) => {
-                let payer_acct = payer_acct.to_mut();
+                let payer_acct = Arc::make_mut(payer_acct);
                 let gas_cost = gas * gas_price;
                 if payer_acct.balance < u128::from(gas_cost) {
                     payer_acct.balance = 0;
@@ -76,11 +171,24 @@ impl<'bc> blockchain_traits::Block for Block<'bc> {
             None => early_return!(InvalidCallee),
         };
 
+        if let Some(caller_acct) = self.state.get_mut(&caller) {
+            Arc::make_mut(caller_acct).nonce += 1;
+        }
+
+        let call_input = match CallInput::open(
+            self.state[&callee].confidential_key.as_ref(),
+            &caller,
+            input,
+        ) {
+            Some(call_input) => call_input,
+            None => early_return!(InvalidInput),
+        };
+
         let mut ptx_state = self.state.clone();
 
         match ptx_state.get_mut(&caller) {
             Some(caller_acct) => {
-                let caller_acct = caller_acct.to_mut();
+                let caller_acct = Arc::make_mut(caller_acct);
                 if caller_acct.balance < value {
                     early_return!(InsufficientFunds);
                 }
@@ -89,29 +197,60 @@ impl<'bc> blockchain_traits::Block for Block<'bc> {
             None => early_return!(InvalidCallee),
         };
 
-        ptx_state.get_mut(&callee).unwrap().to_mut().balance += value;
+        Arc::make_mut(ptx_state.get_mut(&callee).unwrap()).balance += value;
+
+        let gas_left = gas - self.base_gas;
+        let mut receipt = self.run(
+            receipt,
+            payer,
+            ptx_state,
+            call_input.input,
+            call_input.aad,
+            gas_left,
+            gas_price,
+        );
+        if let Some(sealer) = call_input.sealer {
+            sealer.seal_receipt(&mut receipt);
+        }
+        receipt
+    }
 
+    /// Invokes the entrypoint of `receipt.callee` and, if the invocation succeeds, commits
+    /// `ptx_state` along with any changes made by the callee.
+    #[allow(clippy::too_many_arguments)]
+    fn run(
+        &mut self,
+        mut receipt: Receipt,
+        payer: Address,
+        ptx_state: State,
+        input: Vec<u8>,
+        aad: Vec<u8>,
+        gas_left: u64,
+        gas_price: u64,
+    ) -> Receipt {
         let mut pending_transaction = PendingTransaction {
-            caller,
-            callee,
-            value,
-            input: input.to_vec(),
+            caller: receipt.caller,
+            callee: receipt.callee,
+            payer,
+            value: receipt.value,
+            input,
+            aad,
             outcome: TransactionOutcome::Success,
             state: ptx_state,
             events: Vec::new(),
+            caller_events: Vec::new(),
             output: Vec::new(),
             base_gas: self.base_gas,
-            gas_left: gas - self.base_gas,
+            storage_price: self.storage_price,
+            gas_left,
+            gas_price,
+            height: self.height,
+            timestamp: self.timestamp,
+            scheduled_calls: Vec::new(),
         };
 
-        if let Some(main) = self.state.get(&callee).unwrap().main {
-            let ptx: &mut dyn blockchain_traits::PendingTransaction = &mut pending_transaction;
-            let errno = main(unsafe {
-                // Extend the lifetime, as required by the FFI type.
-                // This is only unsafe if the `main` fn stores the pointer,
-                // but this is disallowed by the precondition on `main`.
-                &(std::mem::transmute::<&mut _, &'static mut _>(ptx) as *mut _) as *const _
-            });
+        if let Some(main) = self.state.get(&receipt.callee).unwrap().main {
+            let errno = main(&mut pending_transaction);
             if errno != 0 {
                 pending_transaction.outcome = TransactionOutcome::Aborted;
             }
@@ -124,6 +263,42 @@ impl<'bc> blockchain_traits::Block for Block<'bc> {
         } else {
             self.state = pending_transaction.state;
             receipt.events.append(&mut pending_transaction.events);
+            self.scheduled_calls
+                .append(&mut pending_transaction.scheduled_calls);
+        }
+        receipt
+    }
+}
+
+impl blockchain_traits::Block for Block {
+    fn height(&self) -> u64 {
+        self.height
+    }
+
+    fn transact(
+        &mut self,
+        caller: Address,
+        callee: Address,
+        payer: Address,
+        value: u128,
+        input: &[u8],
+        gas: u64,
+        gas_price: u64,
+    ) -> Box<dyn blockchain_traits::Receipt> {
+        let receipt = self.execute(caller, callee, payer, value, input, gas, gas_price);
+        if let Some(journal) = &self.journal {
+            journal.record(&Entry::Transact {
+                tx: Transaction {
+                    caller,
+                    callee,
+                    payer,
+                    value,
+                    input: input.to_vec(),
+                    gas,
+                    gas_price,
+                },
+                receipt: receipt.clone(),
+            });
         }
         self.completed_transactions.push(receipt.clone());
         box receipt
@@ -137,6 +312,8 @@ impl<'bc> blockchain_traits::Block for Block<'bc> {
         self.state.get(addr).map(|acct| AccountMeta {
             balance: acct.balance,
             expiry: acct.expiry,
+            storage_size: acct.storage_size(),
+            nonce: acct.nonce,
         })
     }
 
@@ -0,0 +1,242 @@
+//! Encryption for confidential accounts.
+//!
+//! The storage of a confidential account is encrypted at rest under a key derived from the
+//! account's `confidential_key`. Inputs to a confidential account are sealed by the caller
+//! under the key returned by `tx_key`, and the output and events of the transaction are
+//! sealed under the same key and bound to the same AAD.
+//!
+//! The cipher is a keccak-based stream cipher with a keccak MAC. It exists so that services
+//! can be tested for plaintext leaks and must not be used to protect real secrets.
+//!
+//! A sealed message is `nonce:[u8; NONCE_SIZE] aad_len:u32 aad ciphertext tag:[u8; TAG_SIZE]`.
+
+use std::convert::TryInto;
+
+use oasis_types::Address;
+
+use crate::output::Receipt;
+
+pub const KEY_SIZE: usize = 32;
+pub const NONCE_SIZE: usize = 16;
+pub const TAG_SIZE: usize = 16;
+
+pub type Key = [u8; KEY_SIZE];
+pub type Nonce = [u8; NONCE_SIZE];
+
+/// The contents of a sealed message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Opened {
+    pub nonce: Nonce,
+    pub aad: Vec<u8>,
+    pub plaintext: Vec<u8>,
+}
+
+/// Returns the key that `caller` uses to exchange messages with the confidential
+/// account that has `confidential_key`.
+pub fn tx_key(confidential_key: &Key, caller: &Address) -> Key {
+    hash(&[b"tx", confidential_key, &caller.0])
+}
+
+/// Derives a new nonce from `nonce` and `context`.
+pub fn derive_nonce(nonce: &Nonce, context: &[u8]) -> Nonce {
+    truncate(hash(&[b"nonce", nonce, context]))
+}
+
+/// Encrypts `plaintext` and authenticates it along with `aad`.
+pub fn seal(key: &Key, nonce: &Nonce, aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut sealed = Vec::with_capacity(NONCE_SIZE + 4 + aad.len() + plaintext.len() + TAG_SIZE);
+    sealed.extend_from_slice(nonce);
+    sealed.extend_from_slice(&(aad.len() as u32).to_le_bytes());
+    sealed.extend_from_slice(aad);
+    sealed.append(&mut encrypt(key, nonce, aad, plaintext));
+    sealed
+}
+
+/// Decrypts a message created by `seal`. Returns `None` if the message is malformed
+/// or was not sealed using `key`.
+pub fn open(key: &Key, sealed: &[u8]) -> Option<Opened> {
+    if sealed.len() < NONCE_SIZE + 4 {
+        return None;
+    }
+    let (nonce, rest) = sealed.split_at(NONCE_SIZE);
+    let (aad_len, rest) = rest.split_at(4);
+    let aad_len = u32::from_le_bytes(aad_len.try_into().unwrap()) as usize;
+    if rest.len() < aad_len {
+        return None;
+    }
+    let (aad, ciphertext) = rest.split_at(aad_len);
+    let nonce: Nonce = nonce.try_into().unwrap();
+    decrypt(key, &nonce, aad, ciphertext).map(|plaintext| Opened {
+        nonce,
+        aad: aad.to_vec(),
+        plaintext,
+    })
+}
+
+/// The input of a call to an account, which has been opened if the account is confidential.
+pub(crate) struct CallInput {
+    pub input: Vec<u8>,
+
+    /// The AAD that the caller sealed along with the input. Calls to accounts that aren't
+    /// confidential have no AAD.
+    pub aad: Vec<u8>,
+
+    /// Seals the output of a call to a confidential account for its caller.
+    pub sealer: Option<Sealer>,
+}
+
+impl CallInput {
+    /// Opens the `input` of a call from `caller` to an account that has `confidential_key`,
+    /// if any. Every call to a confidential account, including subtransactions and scheduled
+    /// calls, must be sealed by its caller. Returns `None` if the input is not.
+    pub(crate) fn open(
+        confidential_key: Option<&Key>,
+        caller: &Address,
+        input: &[u8],
+    ) -> Option<Self> {
+        Some(match confidential_key {
+            Some(confidential_key) => {
+                let tx_key = tx_key(confidential_key, caller);
+                let opened = open(&tx_key, input)?;
+                Self {
+                    input: opened.plaintext,
+                    aad: opened.aad.clone(),
+                    sealer: Some(Sealer {
+                        tx_key,
+                        nonce: opened.nonce,
+                        aad: opened.aad,
+                    }),
+                }
+            }
+            None => Self {
+                input: input.to_vec(),
+                aad: Vec::new(),
+                sealer: None,
+            },
+        })
+    }
+}
+
+pub(crate) struct Sealer {
+    tx_key: Key,
+    nonce: Nonce,
+    aad: Vec<u8>,
+}
+
+impl Sealer {
+    /// Seals the output and event data of a call so that they can only be read by the caller.
+    pub(crate) fn seal_receipt(&self, receipt: &mut Receipt) {
+        let output_nonce = derive_nonce(&self.nonce, b"output");
+        receipt.output = seal(&self.tx_key, &output_nonce, &self.aad, &receipt.output);
+        for (i, event) in receipt.events.iter_mut().enumerate() {
+            let event_nonce = derive_nonce(&self.nonce, &(i as u64).to_le_bytes());
+            event.data = seal(&self.tx_key, &event_nonce, &self.aad, &event.data);
+        }
+    }
+}
+
+/// Encrypts a storage key. The encryption is deterministic so that the encrypted
+/// key can be used to look up the value.
+pub(crate) fn seal_storage_key(confidential_key: &Key, key: &[u8]) -> Vec<u8> {
+    let storage_key = storage_key(confidential_key);
+    let nonce = truncate(hash(&[b"storage key", &storage_key, key]));
+    let mut sealed = nonce.to_vec();
+    sealed.append(&mut encrypt(&storage_key, &nonce, &[], key));
+    sealed
+}
+
+pub(crate) fn open_storage_key(confidential_key: &Key, sealed_key: &[u8]) -> Vec<u8> {
+    let (nonce, ciphertext) = sealed_key.split_at(NONCE_SIZE);
+    decrypt(
+        &storage_key(confidential_key),
+        nonce.try_into().unwrap(),
+        &[],
+        ciphertext,
+    )
+    .expect("confidential storage was corrupted")
+}
+
+/// Encrypts a storage value and binds it to its (encrypted) storage key.
+pub(crate) fn seal_storage_value(
+    confidential_key: &Key,
+    sealed_key: &[u8],
+    value: &[u8],
+) -> Vec<u8> {
+    let storage_key = storage_key(confidential_key);
+    let nonce = truncate(hash(&[b"storage value", &storage_key, sealed_key, value]));
+    let mut sealed = nonce.to_vec();
+    sealed.append(&mut encrypt(&storage_key, &nonce, sealed_key, value));
+    sealed
+}
+
+pub(crate) fn open_storage_value(
+    confidential_key: &Key,
+    sealed_key: &[u8],
+    sealed_value: &[u8],
+) -> Vec<u8> {
+    let (nonce, ciphertext) = sealed_value.split_at(NONCE_SIZE);
+    decrypt(
+        &storage_key(confidential_key),
+        nonce.try_into().unwrap(),
+        sealed_key,
+        ciphertext,
+    )
+    .expect("confidential storage was corrupted")
+}
+
+fn storage_key(confidential_key: &Key) -> Key {
+    hash(&[b"storage", confidential_key])
+}
+
+fn encrypt(key: &Key, nonce: &Nonce, aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut ciphertext = plaintext.to_vec();
+    apply_keystream(key, nonce, &mut ciphertext);
+    let tag = tag(key, nonce, aad, &ciphertext);
+    ciphertext.extend_from_slice(&tag);
+    ciphertext
+}
+
+fn decrypt(key: &Key, nonce: &Nonce, aad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+    if ciphertext.len() < TAG_SIZE {
+        return None;
+    }
+    let (ciphertext, expected_tag) = ciphertext.split_at(ciphertext.len() - TAG_SIZE);
+    if tag(key, nonce, aad, ciphertext) != expected_tag {
+        return None;
+    }
+    let mut plaintext = ciphertext.to_vec();
+    apply_keystream(key, nonce, &mut plaintext);
+    Some(plaintext)
+}
+
+fn apply_keystream(key: &Key, nonce: &Nonce, buf: &mut [u8]) {
+    for (i, chunk) in buf.chunks_mut(KEY_SIZE).enumerate() {
+        let keystream = hash(&[b"keystream", key, nonce, &(i as u64).to_le_bytes()]);
+        chunk
+            .iter_mut()
+            .zip(keystream.iter())
+            .for_each(|(b, k)| *b ^= k);
+    }
+}
+
+fn tag(key: &Key, nonce: &Nonce, aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_SIZE] {
+    truncate(hash(&[b"tag", key, nonce, aad, ciphertext]))
+}
+
+/// Hashes the length-prefixed concatenation of `parts`.
+fn hash(parts: &[&[u8]]) -> [u8; 32] {
+    let mut keccak = tiny_keccak::Keccak::new_keccak256();
+    for part in parts {
+        keccak.update(&(part.len() as u64).to_le_bytes());
+        keccak.update(part);
+    }
+    let mut digest = [0u8; 32];
+    keccak.finalize(&mut digest);
+    digest
+}
+
+fn truncate(digest: [u8; 32]) -> [u8; 16] {
+    let mut truncated = [0u8; 16];
+    truncated.copy_from_slice(&digest[..16]);
+    truncated
+}
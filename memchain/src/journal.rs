@@ -0,0 +1,352 @@
+//! An append-only record of the top-level transactions executed by a `Memchain`.
+//!
+//! A journal, together with the genesis state, is enough to deterministically rebuild
+//! the chain using `Memchain::replay`, which also checks that every re-executed
+//! transaction produces the receipt that was originally recorded.
+//!
+//! Format: a sequence of entries, each of which is a one-byte tag followed by its fields.
+//! Integers are little-endian. Byte strings are prefixed by their 32-bit length.
+//! * `0` - block: `height: u64 timestamp: u64 num_scheduled:u32 [scheduled; num_scheduled]`
+//! * `1` - transact: `caller callee payer value:u128 input gas:u64 gas_price:u64 receipt`
+//! * `2` - storage price: `storage_price:u128`
+//!
+//! where `scheduled` is `caller callee value:u128 receipt`, `receipt` is `outcome:u16 gas_used:u64 output num_events:u32 [event; num_events]`
+//! and `event` is `emitter num_topics:u32 [topic:[u8; 32]; num_topics] data`.
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use blockchain_traits::TransactionOutcome;
+use oasis_types::{Address, Event};
+
+use crate::output::Receipt;
+
+const BLOCK_TAG: u8 = 0;
+const TRANSACT_TAG: u8 = 1;
+const STORAGE_PRICE_TAG: u8 = 2;
+
+/// A single record in a journal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Entry {
+    /// A new block was created at `height`, and the calls scheduled for it produced
+    /// `scheduled`, in order of execution.
+    Block {
+        height: u64,
+        timestamp: u64,
+        scheduled: Vec<Receipt>,
+    },
+
+    /// A top-level transaction was executed and produced `receipt`.
+    Transact { tx: Transaction, receipt: Receipt },
+
+    /// The storage price of the chain was set. It's recorded when the journal is attached so
+    /// that a replay charges the same deposits.
+    StoragePrice { storage_price: u128 },
+}
+
+/// The arguments of a call to `Block::transact`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transaction {
+    pub caller: Address,
+    pub callee: Address,
+    pub payer: Address,
+    pub value: u128,
+    pub input: Vec<u8>,
+    pub gas: u64,
+    pub gas_price: u64,
+}
+
+/// A shared handle to the sink into which journal entries are written.
+#[derive(Clone)]
+pub struct Journal(Arc<Mutex<Sink>>);
+
+struct Sink {
+    writer: Box<dyn Write + Send>,
+
+    /// The first error returned by `writer`. No entries are written after an error because
+    /// the journal would no longer be replayable.
+    error: Option<io::Error>,
+}
+
+impl Journal {
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        Self(Arc::new(Mutex::new(Sink {
+            writer: Box::new(writer),
+            error: None,
+        })))
+    }
+
+    /// Creates the journal file at `path`, replacing its contents if it already exists, so
+    /// that the journal never mixes the histories of two chains.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(std::fs::File::create(path)?))
+    }
+
+    /// Flushes the journal. Returns the first error encountered while recording an entry,
+    /// if any, after which the journal is incomplete.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut guard = self.0.lock().unwrap();
+        let sink = &mut *guard;
+        match &sink.error {
+            Some(err) => Err(io::Error::new(err.kind(), err.to_string())),
+            None => sink.writer.flush(),
+        }
+    }
+
+    /// Writes `entry` to the journal. The entry is flushed before returning
+    /// so that the journal survives the process crashing. An error is kept
+    /// and reported by `Journal::flush` so that it doesn't interrupt the chain.
+    pub(crate) fn record(&self, entry: &Entry) {
+        let mut buf = Vec::new();
+        entry.write(&mut buf).unwrap(); // writing to a `Vec` can't fail
+        let mut guard = self.0.lock().unwrap();
+        let sink = &mut *guard;
+        if sink.error.is_some() {
+            return;
+        }
+        if let Err(err) = sink
+            .writer
+            .write_all(&buf)
+            .and_then(|_| sink.writer.flush())
+        {
+            sink.error = Some(err);
+        }
+    }
+}
+
+impl fmt::Debug for Journal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Journal")
+    }
+}
+
+impl PartialEq for Journal {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Journal {}
+
+impl Entry {
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        match self {
+            Entry::Block {
+                height,
+                timestamp,
+                scheduled,
+            } => {
+                w.write_all(&[BLOCK_TAG])?;
+                w.write_all(&height.to_le_bytes())?;
+                w.write_all(&timestamp.to_le_bytes())?;
+                w.write_all(&(scheduled.len() as u32).to_le_bytes())?;
+                for receipt in scheduled.iter() {
+                    w.write_all(&receipt.caller.0)?;
+                    w.write_all(&receipt.callee.0)?;
+                    w.write_all(&receipt.value.to_le_bytes())?;
+                    write_receipt(w, receipt)?;
+                }
+                Ok(())
+            }
+            Entry::Transact { tx, receipt } => {
+                w.write_all(&[TRANSACT_TAG])?;
+                w.write_all(&tx.caller.0)?;
+                w.write_all(&tx.callee.0)?;
+                w.write_all(&tx.payer.0)?;
+                w.write_all(&tx.value.to_le_bytes())?;
+                write_bytes(w, &tx.input)?;
+                w.write_all(&tx.gas.to_le_bytes())?;
+                w.write_all(&tx.gas_price.to_le_bytes())?;
+                write_receipt(w, receipt)
+            }
+            Entry::StoragePrice { storage_price } => {
+                w.write_all(&[STORAGE_PRICE_TAG])?;
+                w.write_all(&storage_price.to_le_bytes())
+            }
+        }
+    }
+
+    /// Reads the next entry from `r`, or returns `None` if `r` is exhausted.
+    pub fn read<R: Read>(r: &mut R) -> io::Result<Option<Self>> {
+        let mut tag = [0u8];
+        loop {
+            match r.read(&mut tag) {
+                Ok(0) => return Ok(None),
+                Ok(_) => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(Some(match tag[0] {
+            BLOCK_TAG => {
+                let height = read_u64(r)?;
+                let timestamp = read_u64(r)?;
+                let num_scheduled = read_u32(r)?;
+                let mut scheduled = Vec::new();
+                for _ in 0..num_scheduled {
+                    let caller = read_address(r)?;
+                    let callee = read_address(r)?;
+                    let value = read_u128(r)?;
+                    scheduled.push(read_receipt(r, caller, callee, value)?);
+                }
+                Entry::Block {
+                    height,
+                    timestamp,
+                    scheduled,
+                }
+            }
+            TRANSACT_TAG => {
+                let caller = read_address(r)?;
+                let callee = read_address(r)?;
+                let tx = Transaction {
+                    caller,
+                    callee,
+                    payer: read_address(r)?,
+                    value: read_u128(r)?,
+                    input: read_bytes(r)?,
+                    gas: read_u64(r)?,
+                    gas_price: read_u64(r)?,
+                };
+                let receipt = read_receipt(r, caller, callee, tx.value)?;
+                Entry::Transact { tx, receipt }
+            }
+            STORAGE_PRICE_TAG => Entry::StoragePrice {
+                storage_price: read_u128(r)?,
+            },
+            tag => return Err(invalid_data(format!("unknown journal entry tag: {}", tag))),
+        }))
+    }
+}
+
+fn write_receipt<W: Write>(w: &mut W, receipt: &Receipt) -> io::Result<()> {
+    w.write_all(&(receipt.outcome as u16).to_le_bytes())?;
+    w.write_all(&receipt.gas_used.to_le_bytes())?;
+    write_bytes(w, &receipt.output)?;
+    w.write_all(&(receipt.events.len() as u32).to_le_bytes())?;
+    for event in receipt.events.iter() {
+        w.write_all(&event.emitter.0)?;
+        w.write_all(&(event.topics.len() as u32).to_le_bytes())?;
+        for topic in event.topics.iter() {
+            w.write_all(topic)?;
+        }
+        write_bytes(w, &event.data)?;
+    }
+    Ok(())
+}
+
+fn read_receipt<R: Read>(
+    r: &mut R,
+    caller: Address,
+    callee: Address,
+    value: u128,
+) -> io::Result<Receipt> {
+    let outcome = outcome_from_u16(read_u16(r)?)?;
+    let gas_used = read_u64(r)?;
+    let output = read_bytes(r)?;
+    let num_events = read_u32(r)?;
+    let mut events = Vec::new();
+    for _ in 0..num_events {
+        let emitter = read_address(r)?;
+        let num_topics = read_u32(r)?;
+        let mut topics = Vec::new();
+        for _ in 0..num_topics {
+            let mut topic = [0u8; 32];
+            r.read_exact(&mut topic)?;
+            topics.push(topic);
+        }
+        events.push(Event {
+            emitter,
+            topics,
+            data: read_bytes(r)?,
+        });
+    }
+    Ok(Receipt {
+        outcome,
+        caller,
+        callee,
+        value,
+        gas_used,
+        events,
+        output,
+    })
+}
+
+fn outcome_from_u16(outcome: u16) -> io::Result<TransactionOutcome> {
+    use TransactionOutcome::*;
+    [
+        Success,
+        InsufficientFunds,
+        InsufficientGas,
+        InvalidInput,
+        InvalidCallee,
+        Aborted,
+        Fatal,
+    ]
+    .iter()
+    .copied()
+    .find(|o| *o as u16 == outcome)
+    .ok_or_else(|| invalid_data(format!("unknown transaction outcome: {}", outcome)))
+}
+
+fn write_bytes<W: Write>(w: &mut W, bytes: &[u8]) -> io::Result<()> {
+    w.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    w.write_all(bytes)
+}
+
+fn read_bytes<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0u8; read_u32(r)? as usize];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_address<R: Read>(r: &mut R) -> io::Result<Address> {
+    let mut addr = Address::default();
+    r.read_exact(&mut addr.0)?;
+    Ok(addr)
+}
+
+macro_rules! read_int {
+    ($($fn:ident -> $int:ty),+) => {
+        $(
+            fn $fn<R: Read>(r: &mut R) -> io::Result<$int> {
+                let mut bytes = [0u8; std::mem::size_of::<$int>()];
+                r.read_exact(&mut bytes)?;
+                Ok(<$int>::from_le_bytes(bytes))
+            }
+        )+
+    }
+}
+
+read_int!(read_u16 -> u16, read_u32 -> u32, read_u64 -> u64, read_u128 -> u128);
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("could not read journal: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("journal created block {expected}, but replay created block {actual}")]
+    BlockMismatch { expected: u64, actual: u64 },
+
+    #[error("block {height} executed {actual} scheduled calls, but {expected} were recorded")]
+    ScheduledCallsMismatch {
+        height: u64,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("transaction {index} of block {height} produced a different receipt")]
+    ReceiptMismatch {
+        height: u64,
+        index: usize,
+        expected: Box<Receipt>,
+        actual: Box<Receipt>,
+    },
+}
@@ -2,54 +2,217 @@
 #![feature(box_syntax)]
 
 mod block;
+pub mod confidential;
+pub mod journal;
 mod output;
 mod pending_transaction;
+mod schedule;
 
-use std::{borrow::Cow, collections::HashMap, convert::TryInto};
+use std::{borrow::Cow, collections::HashMap, convert::TryInto, fmt, io::Read, sync::Arc};
 
-use blockchain_traits::Blockchain;
+use blockchain_traits::{Block as _, Blockchain};
 use oasis_types::Address;
 
 pub use block::Block;
+pub use journal::{Journal, ReplayError};
+pub use output::Receipt;
+pub use schedule::ScheduledCall;
 
-type State<'bc> = HashMap<Address, Cow<'bc, Account>>;
+/// Accounts are reference counted so that blocks and pending transactions can cheaply
+/// share them. An account is cloned only when it's first modified (@see `Arc::make_mut`).
+type State = HashMap<Address, Arc<Account>>;
 
-pub type PtxPtr = *const *mut dyn blockchain_traits::PendingTransaction;
-pub type AccountMain = extern "C" fn(PtxPtr) -> u16;
+/// The time between blocks created by `Memchain::create_block`, in nanoseconds.
+pub const BLOCK_INTERVAL: u64 = 1_000_000_000;
+
+pub type AccountMain = fn(&mut dyn blockchain_traits::PendingTransaction) -> u16;
 
 #[derive(Debug)]
-pub struct Memchain<'bc> {
+pub struct Memchain {
     pub name: String,
-    pub blocks: Vec<Block<'bc>>,
+    pub blocks: Vec<Block>,
     pub base_gas: u64,
+
+    /// The deposit charged per byte of storage written. @see `Memchain::with_storage_price`.
+    pub storage_price: u128,
+
+    /// Records every top-level transaction and new block, when set. @see `Memchain::replay`.
+    pub journal: Option<Journal>,
 }
 
-impl<'bc> Memchain<'bc> {
-    pub fn new<S: AsRef<str>>(name: S, genesis_state: State<'bc>, base_gas: u64) -> Self {
+impl Memchain {
+    /// Creates a new chain from `genesis_state`. The storage of confidential accounts in the
+    /// genesis state is given in plaintext and is encrypted when the chain is created.
+    /// The genesis block has a timestamp of zero so that the chain can be replayed.
+    pub fn new<S: AsRef<str>>(
+        name: S,
+        genesis_state: HashMap<Address, Account>,
+        base_gas: u64,
+    ) -> Self {
         let mut bc = Self {
             name: name.as_ref().to_string(),
             blocks: Vec::new(),
             base_gas,
+            storage_price: 0,
+            journal: None,
         };
-        bc.create_block_with_state(genesis_state);
+        bc.create_block_with_state(
+            genesis_state
+                .into_iter()
+                .map(|(addr, mut acct)| {
+                    if acct.confidential_key.is_some() {
+                        let storage = std::mem::take(&mut acct.storage);
+                        for (key, value) in storage.iter() {
+                            blockchain_traits::KVStoreMut::set(&mut acct, key, value);
+                        }
+                    }
+                    (addr, Arc::new(acct))
+                })
+                .collect(),
+            0, /* timestamp */
+        );
         bc
     }
 
-    pub fn create_block(&mut self) -> &mut Block<'bc> {
-        self.create_block_with_state(self.blocks.last().unwrap().state.clone())
+    /// Charges accounts a deposit of `storage_price` per byte that they add to their storage.
+    /// The deposit that was paid for an entry is refunded when the entry is removed or
+    /// overwritten.
+    pub fn with_storage_price(mut self, storage_price: u128) -> Self {
+        self.set_storage_price(storage_price);
+        if let Some(jnl) = &self.journal {
+            jnl.record(&journal::Entry::StoragePrice { storage_price });
+        }
+        self
+    }
+
+    /// Records the storage price and all subsequent blocks and top-level transactions
+    /// to `journal`.
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        journal.record(&journal::Entry::StoragePrice {
+            storage_price: self.storage_price,
+        });
+        self.blocks.last_mut().unwrap().journal = Some(journal.clone());
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Rebuilds a chain from its genesis state and the contents of a `Journal`.
+    /// Returns an error if any replayed transaction or scheduled call produces a receipt that
+    /// differs from the one that was recorded.
+    pub fn replay<S: AsRef<str>, R: Read>(
+        name: S,
+        genesis_state: HashMap<Address, Account>,
+        base_gas: u64,
+        mut reader: R,
+    ) -> Result<Self, ReplayError> {
+        let mut bc = Self::new(name, genesis_state, base_gas);
+        while let Some(entry) = journal::Entry::read(&mut reader)? {
+            match entry {
+                journal::Entry::Block {
+                    height,
+                    timestamp,
+                    scheduled,
+                } => {
+                    let block = bc.create_block_with_timestamp(timestamp);
+                    if block.height != height {
+                        return Err(ReplayError::BlockMismatch {
+                            expected: height,
+                            actual: block.height,
+                        });
+                    }
+                    if block.completed_transactions.len() != scheduled.len() {
+                        return Err(ReplayError::ScheduledCallsMismatch {
+                            height,
+                            expected: scheduled.len(),
+                            actual: block.completed_transactions.len(),
+                        });
+                    }
+                    let receipts = scheduled.into_iter().zip(&block.completed_transactions);
+                    for (index, (receipt, actual)) in receipts.enumerate() {
+                        if *actual != receipt {
+                            return Err(ReplayError::ReceiptMismatch {
+                                height,
+                                index,
+                                expected: box receipt,
+                                actual: box actual.clone(),
+                            });
+                        }
+                    }
+                }
+                journal::Entry::Transact { tx, receipt } => {
+                    let block = bc.blocks.last_mut().unwrap();
+                    block.transact(
+                        tx.caller,
+                        tx.callee,
+                        tx.payer,
+                        tx.value,
+                        &tx.input,
+                        tx.gas,
+                        tx.gas_price,
+                    );
+                    let actual = block.completed_transactions.last().unwrap();
+                    if *actual != receipt {
+                        return Err(ReplayError::ReceiptMismatch {
+                            height: block.height,
+                            index: block.completed_transactions.len() - 1,
+                            expected: box receipt,
+                            actual: box actual.clone(),
+                        });
+                    }
+                }
+                journal::Entry::StoragePrice { storage_price } => {
+                    bc.set_storage_price(storage_price)
+                }
+            }
+        }
+        Ok(bc)
+    }
+
+    /// Creates a new block from the state of the last block and executes the calls
+    /// that were scheduled for the new block's height.
+    /// The block's timestamp is `BLOCK_INTERVAL` after that of the last block, which keeps
+    /// the chain deterministic and independent of the host's clock.
+    pub fn create_block(&mut self) -> &mut Block {
+        let timestamp = self.blocks.last().unwrap().timestamp;
+        self.create_block_with_timestamp(timestamp.saturating_add(BLOCK_INTERVAL))
+    }
+
+    /// Like `create_block`, but the new block has the provided `timestamp`, in nanoseconds
+    /// since the Unix epoch. The timestamp of a block is never less than that of its parent.
+    pub fn create_block_with_timestamp(&mut self, timestamp: u64) -> &mut Block {
+        let prev_block = self.blocks.last().unwrap();
+        let state = prev_block.state.clone();
+        let scheduled_calls = prev_block.scheduled_calls.clone();
+        let timestamp = std::cmp::max(timestamp, prev_block.timestamp);
+        let block = self.create_block_with_state(state, timestamp);
+        block.scheduled_calls = scheduled_calls;
+        block.execute_scheduled_calls();
+        if let Some(jnl) = &block.journal {
+            jnl.record(&journal::Entry::Block {
+                height: block.height,
+                timestamp,
+                scheduled: block.completed_transactions.clone(),
+            });
+        }
+        block
+    }
+
+    fn set_storage_price(&mut self, storage_price: u128) {
+        self.blocks.last_mut().unwrap().storage_price = storage_price;
+        self.storage_price = storage_price;
     }
 
-    fn create_block_with_state(&mut self, state: State<'bc>) -> &mut Block<'bc> {
-        self.blocks.push(Block::new(
-            self.blocks.len().try_into().unwrap(),
-            state,
-            self.base_gas,
-        ));
+    fn create_block_with_state(&mut self, state: State, timestamp: u64) -> &mut Block {
+        let mut block = Block::new(self.blocks.len().try_into().unwrap(), state, self.base_gas);
+        block.timestamp = timestamp;
+        block.storage_price = self.storage_price;
+        block.journal = self.journal.clone();
+        self.blocks.push(block);
         self.blocks.last_mut().unwrap()
     }
 }
 
-impl<'bc> Blockchain for Memchain<'bc> {
+impl Blockchain for Memchain {
     fn name(&self) -> &str {
         &self.name
     }
@@ -69,37 +232,130 @@ impl<'bc> Blockchain for Memchain<'bc> {
     }
 }
 
-#[derive(Clone, Default, Debug, PartialEq, Eq)]
+#[derive(Clone, Default)]
 pub struct Account {
     pub balance: u128,
     pub code: Vec<u8>,
     pub storage: HashMap<Vec<u8>, Vec<u8>>,
+
+    /// The deposit that was paid for each entry of `storage`, by storage key. Entries that
+    /// weren't written by a transaction, such as those in the genesis state, have no deposit.
+    pub storage_deposits: HashMap<Vec<u8>, u128>,
+
     pub expiry: Option<std::time::Duration>,
 
-    /// Callable account entrypoint. `main` takes an pointer to a
-    /// `Blockchain` trait object which can be used via FFI bindings
-    /// to interact with the memchain. Returns nonzero to revert transaction.
-    /// This pointer is not valid after the call to `main` has returned.
+    /// The number of transactions sent and accounts created by the account.
+    pub nonce: u64,
+
+    /// Makes the account confidential when set. The `storage` of a confidential account
+    /// is encrypted at rest, and transactions to it must be sealed by the caller.
+    /// @see the `confidential` module.
+    pub confidential_key: Option<confidential::Key>,
+
+    /// Callable account entrypoint. `main` receives the `PendingTransaction`
+    /// through which it interacts with the memchain, and which it may not retain
+    /// after returning. Returns nonzero to revert transaction.
     pub main: Option<AccountMain>,
 }
 
+impl fmt::Debug for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Account")
+            .field("balance", &self.balance)
+            .field("code", &self.code)
+            .field("storage", &self.storage)
+            .field("storage_deposits", &self.storage_deposits)
+            .field("expiry", &self.expiry)
+            .field("nonce", &self.nonce)
+            .field("confidential", &self.confidential_key.is_some())
+            .field("main", &self.main.map(|main| main as usize))
+            .finish()
+    }
+}
+
+impl PartialEq for Account {
+    fn eq(&self, other: &Self) -> bool {
+        self.balance == other.balance
+            && self.code == other.code
+            && self.storage == other.storage
+            && self.storage_deposits == other.storage_deposits
+            && self.expiry == other.expiry
+            && self.nonce == other.nonce
+            && self.confidential_key == other.confidential_key
+            && self.main.map(|main| main as usize) == other.main.map(|main| main as usize)
+    }
+}
+
+impl Eq for Account {}
+
+impl Account {
+    /// Returns the number of bytes occupied by the account's storage.
+    pub fn storage_size(&self) -> u64 {
+        self.storage
+            .iter()
+            .map(|(k, v)| (k.len() + v.len()) as u64)
+            .sum()
+    }
+
+    /// Returns the key under which `key` is found in `self.storage`.
+    fn storage_key<'a>(&self, key: &'a [u8]) -> Cow<'a, [u8]> {
+        match &self.confidential_key {
+            Some(confidential_key) => {
+                Cow::Owned(confidential::seal_storage_key(confidential_key, key))
+            }
+            None => Cow::Borrowed(key),
+        }
+    }
+}
+
 impl blockchain_traits::KVStore for Account {
     fn contains(&self, key: &[u8]) -> bool {
-        self.storage.contains_key(key)
+        self.storage.contains_key(&*self.storage_key(key))
     }
 
     fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
-        self.storage.get(key).map(Vec::to_owned)
+        let storage_key = self.storage_key(key);
+        self.storage
+            .get(&*storage_key)
+            .map(|value| match &self.confidential_key {
+                Some(confidential_key) => {
+                    confidential::open_storage_value(confidential_key, &storage_key, value)
+                }
+                None => value.to_owned(),
+            })
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Vec<Vec<u8>> {
+        let mut keys: Vec<Vec<u8>> = self
+            .storage
+            .keys()
+            .map(|key| match &self.confidential_key {
+                Some(confidential_key) => confidential::open_storage_key(confidential_key, key),
+                None => key.to_owned(),
+            })
+            .filter(|key| key.starts_with(prefix))
+            .collect();
+        keys.sort();
+        keys
     }
 }
 
 impl blockchain_traits::KVStoreMut for Account {
     fn set(&mut self, key: &[u8], value: &[u8]) {
-        self.storage.insert(key.to_vec(), value.to_vec());
+        let storage_key = self.storage_key(key).into_owned();
+        let value = match &self.confidential_key {
+            Some(confidential_key) => {
+                confidential::seal_storage_value(confidential_key, &storage_key, value)
+            }
+            None => value.to_vec(),
+        };
+        self.storage.insert(storage_key, value);
     }
 
     fn remove(&mut self, key: &[u8]) {
-        self.storage.remove(key);
+        let storage_key = self.storage_key(key);
+        self.storage.remove(&*storage_key);
+        self.storage_deposits.remove(&*storage_key);
     }
 }
 
@@ -1,23 +1,44 @@
-use blockchain_traits::TransactionOutcome;
+use std::sync::Arc;
+
+use blockchain_traits::{KVStoreMut as _, TransactionOutcome};
 use oasis_types::{AccountMeta, Address, Event};
 
-use crate::{output::Receipt, State};
+use crate::{confidential::CallInput, output::Receipt, schedule::ScheduledCall, Account, State};
 
 #[derive(Debug)]
-pub struct PendingTransaction<'bc> {
+pub struct PendingTransaction {
     pub caller: Address,
     pub callee: Address,
+    pub payer: Address,
     pub value: u128,
-    pub state: State<'bc>,
+    pub state: State,
     pub input: Vec<u8>,
+    pub aad: Vec<u8>,
     pub outcome: TransactionOutcome,
     pub output: Vec<u8>,
     pub events: Vec<Event>,
+
+    /// The events emitted earlier in the call tree by the callers of this transaction. The
+    /// events of a confidential caller are omitted because they're sealed only when its
+    /// transaction returns.
+    pub caller_events: Vec<Event>,
+
     pub gas_left: u64,
     pub base_gas: u64,
+    pub gas_price: u64,
+    pub storage_price: u128,
+
+    /// The height of the block in which this transaction is executing.
+    pub height: u64,
+
+    /// The timestamp of the block in which this transaction is executing.
+    pub timestamp: u64,
+
+    /// Calls scheduled by this transaction. They're added to the block if it succeeds.
+    pub scheduled_calls: Vec<ScheduledCall>,
 }
 
-impl<'bc> blockchain_traits::PendingTransaction for PendingTransaction<'bc> {
+impl blockchain_traits::PendingTransaction for PendingTransaction {
     fn address(&self) -> &Address {
         &self.callee
     }
@@ -26,6 +47,10 @@ impl<'bc> blockchain_traits::PendingTransaction for PendingTransaction<'bc> {
         &self.caller
     }
 
+    fn payer(&self) -> &Address {
+        &self.payer
+    }
+
     fn value(&self) -> u128 {
         self.value
     }
@@ -34,8 +59,58 @@ impl<'bc> blockchain_traits::PendingTransaction for PendingTransaction<'bc> {
         self.input.as_slice()
     }
 
-    fn create(&mut self, _value: u128, _code: &[u8]) -> Box<dyn blockchain_traits::Receipt> {
-        unimplemented!();
+    fn aad(&self) -> &[u8] {
+        self.aad.as_slice()
+    }
+
+    fn block_height(&self) -> u64 {
+        self.height
+    }
+
+    fn block_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Creates an account at an address derived from the address and nonce of the current
+    /// account, whose nonce is then incremented. memchain can't run bytecode, so the new account
+    /// has no `main` and isn't initialized by its code.
+    fn create(&mut self, value: u128, code: &[u8]) -> Box<dyn blockchain_traits::Receipt> {
+        let caller = self.callee;
+        let caller_acct = &self.state[&caller];
+        let callee = created_address(&caller, caller_acct.nonce);
+        let mut receipt = Receipt {
+            caller,
+            callee,
+            value,
+            gas_used: 0, // TODO(#116)
+            output: Vec::new(),
+            events: Vec::new(),
+            outcome: TransactionOutcome::Success,
+        };
+
+        if self.gas_left < self.base_gas {
+            receipt.outcome = TransactionOutcome::InsufficientGas;
+        } else if self.state.contains_key(&callee) {
+            receipt.outcome = TransactionOutcome::InvalidCallee;
+        } else if caller_acct.balance < value {
+            receipt.outcome = TransactionOutcome::InsufficientFunds;
+        }
+        if receipt.outcome != TransactionOutcome::Success {
+            return box receipt;
+        }
+
+        let caller_acct = Arc::make_mut(self.state.get_mut(&caller).unwrap());
+        caller_acct.balance -= value;
+        caller_acct.nonce += 1;
+        self.state.insert(
+            callee,
+            Arc::new(Account {
+                balance: value,
+                code: code.to_vec(),
+                ..Default::default()
+            }),
+        );
+        box receipt
     }
 
     fn transact(
@@ -60,14 +135,25 @@ impl<'bc> blockchain_traits::PendingTransaction for PendingTransaction<'bc> {
             return box receipt;
         }
 
-        if !self.state.contains_key(&callee) {
-            receipt.outcome = TransactionOutcome::InvalidCallee;
-            return box receipt;
-        }
+        let call_input = match self.state.get(&callee) {
+            Some(callee_acct) => {
+                match CallInput::open(callee_acct.confidential_key.as_ref(), &caller, input) {
+                    Some(call_input) => call_input,
+                    None => {
+                        receipt.outcome = TransactionOutcome::InvalidInput;
+                        return box receipt;
+                    }
+                }
+            }
+            None => {
+                receipt.outcome = TransactionOutcome::InvalidCallee;
+                return box receipt;
+            }
+        };
 
         let mut ptx_state = self.state.clone();
 
-        let caller_acct = ptx_state.get_mut(&caller).unwrap().to_mut();
+        let caller_acct = Arc::make_mut(ptx_state.get_mut(&caller).unwrap());
 
         if caller_acct.balance < value {
             receipt.outcome = TransactionOutcome::InsufficientFunds;
@@ -76,29 +162,36 @@ impl<'bc> blockchain_traits::PendingTransaction for PendingTransaction<'bc> {
             caller_acct.balance -= value
         }
 
-        ptx_state.get_mut(&callee).unwrap().to_mut().balance += value;
+        Arc::make_mut(ptx_state.get_mut(&callee).unwrap()).balance += value;
+
+        let mut caller_events = self.caller_events.clone();
+        if self.state[&caller].confidential_key.is_none() {
+            caller_events.extend(self.events.iter().cloned());
+        }
 
         let mut pending_transaction = PendingTransaction {
             caller: self.callee,
             callee,
+            payer: self.payer,
             value,
-            input: input.to_vec(),
+            input: call_input.input,
+            aad: call_input.aad,
             outcome: TransactionOutcome::Success,
             state: ptx_state,
             events: Vec::new(),
+            caller_events,
             output: Vec::new(),
             base_gas: self.base_gas,
+            storage_price: self.storage_price,
             gas_left: self.gas_left - self.base_gas,
+            gas_price: self.gas_price,
+            height: self.height,
+            timestamp: self.timestamp,
+            scheduled_calls: Vec::new(),
         };
 
         if let Some(main) = self.state.get(&callee).unwrap().main {
-            let ptx: &mut dyn blockchain_traits::PendingTransaction = &mut pending_transaction;
-            let errno = main(unsafe {
-                // Extend the lifetime, as required by the FFI type.
-                // This is only unsafe if the `main` fn stores the pointer,
-                // but this is disallowed by the precondition on `main`.
-                &(std::mem::transmute::<&mut _, &'static mut _>(ptx) as *mut _) as *const _
-            });
+            let errno = main(&mut pending_transaction);
             if errno != 0 {
                 pending_transaction.outcome = TransactionOutcome::Aborted;
             }
@@ -106,18 +199,60 @@ impl<'bc> blockchain_traits::PendingTransaction for PendingTransaction<'bc> {
 
         receipt.outcome = pending_transaction.outcome;
         receipt.output = pending_transaction.output;
-        if blockchain_traits::Receipt::reverted(&receipt) {
-            receipt.events.clear();
-        } else {
+        if !blockchain_traits::Receipt::reverted(&receipt) {
             self.state = pending_transaction.state;
-            receipt
-                .events
-                .append(&mut pending_transaction.events.clone());
-            self.events.append(&mut pending_transaction.events);
+            receipt.events.append(&mut pending_transaction.events);
+            self.scheduled_calls
+                .append(&mut pending_transaction.scheduled_calls);
+        }
+        if let Some(sealer) = call_input.sealer {
+            sealer.seal_receipt(&mut receipt);
         }
+        self.events.extend(receipt.events.iter().cloned());
         box receipt
     }
 
+    fn schedule(
+        &mut self,
+        height: u64,
+        callee: Address,
+        value: u128,
+        input: &[u8],
+        gas: u64,
+    ) -> TransactionOutcome {
+        if height <= self.height {
+            return TransactionOutcome::InvalidInput;
+        }
+
+        if gas < self.base_gas {
+            return TransactionOutcome::InsufficientGas;
+        }
+
+        if !self.state.contains_key(&callee) {
+            return TransactionOutcome::InvalidCallee;
+        }
+
+        let caller_acct = Arc::make_mut(self.state.get_mut(&self.callee).unwrap());
+        let cost = u128::from(gas)
+            .checked_mul(u128::from(self.gas_price))
+            .and_then(|gas_cost| gas_cost.checked_add(value));
+        match cost {
+            Some(cost) if cost <= caller_acct.balance => caller_acct.balance -= cost,
+            _ => return TransactionOutcome::InsufficientFunds,
+        }
+
+        self.scheduled_calls.push(ScheduledCall {
+            height,
+            caller: self.callee,
+            callee,
+            value,
+            input: input.to_vec(),
+            gas,
+            gas_price: self.gas_price,
+        });
+        TransactionOutcome::Success
+    }
+
     fn ret(&mut self, data: &[u8]) {
         assert!(self.output.is_empty());
         self.output = data.to_vec()
@@ -145,15 +280,19 @@ impl<'bc> blockchain_traits::PendingTransaction for PendingTransaction<'bc> {
         });
     }
 
+    fn events(&self) -> Vec<&Event> {
+        self.caller_events
+            .iter()
+            .chain(self.events.iter())
+            .collect()
+    }
+
     fn state(&self) -> &dyn blockchain_traits::KVStore {
         self.state.get(&self.callee).map(|acct| &**acct).unwrap()
     }
 
     fn state_mut(&mut self) -> &mut dyn blockchain_traits::KVStoreMut {
-        self.state
-            .get_mut(&self.callee)
-            .map(std::borrow::Cow::to_mut)
-            .unwrap()
+        self
     }
 
     fn code_at(&self, addr: &Address) -> Option<&[u8]> {
@@ -164,6 +303,107 @@ impl<'bc> blockchain_traits::PendingTransaction for PendingTransaction<'bc> {
         self.state.get(addr).map(|acct| AccountMeta {
             balance: acct.balance,
             expiry: acct.expiry,
+            storage_size: acct.storage_size(),
+            nonce: acct.nonce,
         })
     }
 }
+
+impl PendingTransaction {
+    fn account_mut(&mut self) -> &mut Account {
+        self.state.get_mut(&self.callee).map(Arc::make_mut).unwrap()
+    }
+}
+
+/// The storage of the current account. Writes are charged a deposit of `storage_price` per
+/// byte that they add, and removing or overwriting an entry refunds the deposit that was paid
+/// for it, whatever the current price. If the account can't pay the deposit, the write is
+/// skipped and the transaction will fail with `InsufficientFunds`.
+impl blockchain_traits::KVStore for PendingTransaction {
+    fn contains(&self, key: &[u8]) -> bool {
+        blockchain_traits::PendingTransaction::state(self).contains(key)
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        blockchain_traits::PendingTransaction::state(self).get(key)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Vec<Vec<u8>> {
+        blockchain_traits::PendingTransaction::state(self).scan_prefix(prefix)
+    }
+}
+
+impl blockchain_traits::KVStoreMut for PendingTransaction {
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        let storage_price = self.storage_price;
+        let account = self.account_mut();
+        let storage_key = account.storage_key(key).into_owned();
+        let prev_value = account.storage.get(&storage_key).cloned();
+        let refund = account
+            .storage_deposits
+            .get(&storage_key)
+            .copied()
+            .unwrap_or_default();
+        account.set(key, value);
+        let deposit =
+            storage_price.checked_mul(entry_size(&storage_key, account.storage.get(&storage_key)));
+        let balance = deposit.and_then(|deposit| {
+            account
+                .balance
+                .checked_add(refund)?
+                .checked_sub(deposit)
+                .map(|balance| (balance, deposit))
+        });
+        match balance {
+            Some((balance, deposit)) => {
+                account.balance = balance;
+                account.storage_deposits.insert(storage_key, deposit);
+            }
+            None => {
+                match prev_value {
+                    Some(prev_value) => account.storage.insert(storage_key, prev_value),
+                    None => account.storage.remove(&storage_key),
+                };
+                self.outcome = TransactionOutcome::InsufficientFunds;
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        let account = self.account_mut();
+        let storage_key = account.storage_key(key).into_owned();
+        let value = match account.storage.remove(&storage_key) {
+            Some(value) => value,
+            None => return,
+        };
+        let deposit = account.storage_deposits.remove(&storage_key);
+        match account.balance.checked_add(deposit.unwrap_or_default()) {
+            Some(balance) => account.balance = balance,
+            None => {
+                if let Some(deposit) = deposit {
+                    account
+                        .storage_deposits
+                        .insert(storage_key.clone(), deposit);
+                }
+                account.storage.insert(storage_key, value);
+                self.outcome = TransactionOutcome::Fatal;
+            }
+        }
+    }
+}
+
+/// Returns the address of the account created by `creator` when its nonce was `nonce`, which
+/// is the last 20 bytes of the keccak256 digest of `creator || nonce`.
+fn created_address(creator: &Address, nonce: u64) -> Address {
+    let digest = tiny_keccak::keccak256(&[&creator.0[..], &nonce.to_le_bytes()].concat());
+    let mut addr = Address::default();
+    addr.0.copy_from_slice(&digest[12..]);
+    addr
+}
+
+/// Returns the number of bytes occupied by a stored entry.
+fn entry_size(storage_key: &[u8], value: Option<&Vec<u8>>) -> u128 {
+    value
+        .map(|v| (storage_key.len() + v.len()) as u128)
+        .unwrap_or_default()
+}
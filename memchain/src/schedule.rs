@@ -0,0 +1,14 @@
+use oasis_types::Address;
+
+/// A call that will be executed at the start of the block at `height`.
+/// The `value` and `gas` (at `gas_price`) were paid by the `caller` when the call was scheduled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScheduledCall {
+    pub height: u64,
+    pub caller: Address,
+    pub callee: Address,
+    pub value: u128,
+    pub input: Vec<u8>,
+    pub gas: u64,
+    pub gas_price: u64,
+}
@@ -13,13 +13,11 @@ fn giga(num: u128) -> u128 {
     num * 1_000_000_000
 }
 
-extern "C" fn nop_main(_ptx: *const *mut dyn PendingTransaction) -> u16 {
+fn nop_main(_ptx: &mut dyn PendingTransaction) -> u16 {
     0
 }
 
-extern "C" fn simple_main(ptx: *const *mut dyn PendingTransaction) -> u16 {
-    let ptx = unsafe { &mut **ptx };
-
+fn simple_main(ptx: &mut dyn PendingTransaction) -> u16 {
     assert_eq!(ptx.sender(), &ADDR_2);
 
     ptx.emit(vec![[42u8; 32].as_ref()].as_slice(), &[0u8; 3]);
@@ -31,14 +29,12 @@ extern "C" fn simple_main(ptx: *const *mut dyn PendingTransaction) -> u16 {
     0
 }
 
-extern "C" fn fail_main(ptx: *const *mut dyn PendingTransaction) -> u16 {
-    let ptx = unsafe { &mut **ptx };
+fn fail_main(ptx: &mut dyn PendingTransaction) -> u16 {
     ptx.err(r"¯\_(ツ)_/¯".as_bytes());
     1
 }
 
-extern "C" fn subtx_main(ptx: *const *mut dyn PendingTransaction) -> u16 {
-    let ptx = unsafe { &mut **ptx };
+fn subtx_main(ptx: &mut dyn PendingTransaction) -> u16 {
     let subtx = ptx.transact(ADDR_1, 0 /* value */, &ptx.input().to_vec());
 
     if subtx.reverted() {
@@ -54,17 +50,19 @@ extern "C" fn subtx_main(ptx: *const *mut dyn PendingTransaction) -> u16 {
     0
 }
 
-fn create_bc<'bc>(
-    mains: Vec<Option<extern "C" fn(*const *mut dyn PendingTransaction) -> u16>>,
-) -> Memchain<'bc> {
-    let genesis_state = mains
+fn create_bc(mains: Vec<Option<AccountMain>>) -> Memchain {
+    Memchain::new("memchain".to_string(), genesis_state(mains), BASE_GAS)
+}
+
+fn genesis_state(mains: Vec<Option<AccountMain>>) -> HashMap<Address, Account> {
+    mains
         .into_iter()
         .enumerate()
         .map(|(i, main)| {
             let i = i + 1;
             (
                 Address([i as u8; 20]),
-                Cow::Owned(Account {
+                Account {
                     balance: giga(i as u128),
                     code: format!("\0asm not wasm {}", i).into_bytes(),
                     storage: {
@@ -79,14 +77,15 @@ fn create_bc<'bc>(
                         );
                         storage
                     },
+                    storage_deposits: HashMap::new(),
                     expiry: None,
+                    nonce: 0,
+                    confidential_key: None,
                     main,
-                }),
+                },
             )
         })
-        .collect();
-
-    Memchain::new("memchain".to_string(), genesis_state, BASE_GAS)
+        .collect()
 }
 
 #[test]
@@ -118,6 +117,7 @@ fn static_account() {
     let mut bc = create_bc(vec![None, None]);
 
     bc.create_block(); // should take state from prev block
+    assert_eq!(bc.last_block().timestamp, BLOCK_INTERVAL);
 
     assert_eq!(
         bc.last_block().account_meta_at(&ADDR_1).unwrap().balance,
@@ -233,3 +233,601 @@ fn subtx_revert() {
         Some(b"common_value".to_vec())
     );
 }
+
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn journal_replay() {
+    let mains: Vec<Option<AccountMain>> = vec![Some(simple_main), Some(subtx_main)];
+    let buf = SharedBuf::default();
+    let mut bc = create_bc(mains.clone()).with_journal(Journal::new(buf.clone()));
+
+    bc.last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_2, 1000, &[1, 2, 3], BASE_GAS * 2, 1);
+    bc.create_block();
+    bc.last_block_mut()
+        .transact(ADDR_2, ADDR_1, ADDR_1, 50, &[4, 5], BASE_GAS, 1);
+    bc.last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_1, 0, &[6], BASE_GAS, 1); // subtx runs out of gas
+    bc.create_block();
+
+    let journal = buf.0.lock().unwrap().clone();
+    let replayed = Memchain::replay(
+        "memchain",
+        genesis_state(mains),
+        BASE_GAS,
+        journal.as_slice(),
+    )
+    .unwrap();
+
+    assert_eq!(replayed.blocks.len(), bc.blocks.len());
+    for (replayed_block, block) in replayed.blocks.iter().zip(bc.blocks.iter()) {
+        assert_eq!(replayed_block.state, block.state);
+        assert_eq!(
+            replayed_block.completed_transactions,
+            block.completed_transactions
+        );
+    }
+}
+
+#[test]
+fn journal_replay_mismatch() {
+    let buf = SharedBuf::default();
+    let mut bc = create_bc(vec![Some(simple_main), None]).with_journal(Journal::new(buf.clone()));
+    bc.last_block_mut()
+        .transact(ADDR_2, ADDR_1, ADDR_1, 50, &[1, 2, 3], BASE_GAS, 0);
+
+    let journal = buf.0.lock().unwrap().clone();
+    match Memchain::replay(
+        "memchain",
+        genesis_state(vec![Some(fail_main), None]),
+        BASE_GAS,
+        journal.as_slice(),
+    ) {
+        Err(ReplayError::ReceiptMismatch {
+            height,
+            index,
+            expected,
+            actual,
+        }) => {
+            assert_eq!((height, index), (0, 0));
+            assert_eq!(expected.output, &[1, 2, 3, 4]);
+            assert!(blockchain_traits::Receipt::reverted(&*actual));
+        }
+        res => panic!("unexpected replay result: {:?}", res.map(|_| ())),
+    }
+}
+
+struct BrokenPipe;
+
+impl std::io::Write for BrokenPipe {
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn journal_write_error() {
+    let journal = Journal::new(BrokenPipe);
+    let mut bc = create_bc(vec![None, Some(nop_main)]).with_journal(journal.clone());
+    let receipt = bc
+        .last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_1, 50, &[], BASE_GAS, 0);
+    assert_eq!(
+        receipt.outcome(),
+        blockchain_traits::TransactionOutcome::Success
+    );
+    bc.create_block();
+    assert_eq!(
+        journal.flush().unwrap_err().kind(),
+        std::io::ErrorKind::BrokenPipe
+    );
+}
+
+/// A reader that is interrupted before every successful read.
+struct InterruptedReader<'a>(&'a [u8], bool);
+
+impl std::io::Read for InterruptedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.1 = !self.1;
+        if self.1 {
+            return Err(std::io::ErrorKind::Interrupted.into());
+        }
+        self.0.read(buf)
+    }
+}
+
+#[test]
+fn journal_read_interrupted() {
+    let entry = journal::Entry::Block {
+        height: 1,
+        timestamp: 2,
+        scheduled: Vec::new(),
+    };
+    let mut buf = Vec::new();
+    entry.write(&mut buf).unwrap();
+    let mut reader = InterruptedReader(&buf, false);
+    assert_eq!(journal::Entry::read(&mut reader).unwrap(), Some(entry));
+    assert_eq!(journal::Entry::read(&mut reader).unwrap(), None);
+}
+
+#[test]
+fn shared_between_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Memchain>();
+
+    let bc = Arc::new(std::sync::Mutex::new(create_bc(vec![None, Some(nop_main)])));
+    let value = 50;
+    let num_threads = 4;
+    let handles: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let bc = Arc::clone(&bc);
+            std::thread::spawn(move || {
+                bc.lock().unwrap().last_block_mut().transact(
+                    ADDR_1,
+                    ADDR_2,
+                    ADDR_1,
+                    value,
+                    &Vec::new(),
+                    BASE_GAS,
+                    0,
+                );
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(
+        bc.lock()
+            .unwrap()
+            .last_block()
+            .account_meta_at(&ADDR_2)
+            .unwrap()
+            .balance,
+        giga(2) + value * num_threads,
+    );
+}
+
+fn create_main(ptx: &mut dyn PendingTransaction) -> u16 {
+    let mut addrs = Vec::new();
+    for code in [&b"\0asm code a"[..], &b"\0asm code b"[..]].iter() {
+        let receipt = ptx.create(100 /* value */, code);
+        assert_eq!(
+            receipt.outcome(),
+            blockchain_traits::TransactionOutcome::Success
+        );
+        assert_eq!(ptx.code_at(receipt.callee()), Some(*code));
+        addrs.extend_from_slice(receipt.callee().as_ref());
+    }
+    let receipt = ptx.create(giga(100), b"");
+    assert_eq!(
+        receipt.outcome(),
+        blockchain_traits::TransactionOutcome::InsufficientFunds
+    );
+    ptx.ret(&addrs);
+    0
+}
+
+#[test]
+fn create() {
+    let mut bc = create_bc(vec![None, Some(create_main)]);
+    let receipt = bc
+        .last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_1, 0, &[], BASE_GAS, 0);
+    assert_eq!(
+        receipt.outcome(),
+        blockchain_traits::TransactionOutcome::Success
+    );
+
+    let addrs: Vec<Address> = receipt
+        .output()
+        .chunks(Address::size())
+        .map(|bytes| {
+            let mut addr = Address::default();
+            addr.0.copy_from_slice(bytes);
+            addr
+        })
+        .collect();
+    assert_ne!(addrs[0], addrs[1]);
+    for (addr, code) in addrs
+        .iter()
+        .zip(&[&b"\0asm code a"[..], &b"\0asm code b"[..]])
+    {
+        assert_eq!(bc.last_block().code_at(addr), Some(*code));
+        assert_eq!(bc.last_block().account_meta_at(addr).unwrap().balance, 100);
+    }
+    assert_eq!(
+        bc.last_block().account_meta_at(&ADDR_2).unwrap().balance,
+        giga(2) - 200
+    );
+}
+
+fn schedule_main(ptx: &mut dyn PendingTransaction) -> u16 {
+    let height = ptx.block_height() + 2;
+    let outcome = ptx.schedule(height, ADDR_1, 100 /* value */, &[1, 2, 3], BASE_GAS);
+    assert_eq!(outcome, blockchain_traits::TransactionOutcome::Success);
+    0
+}
+
+#[test]
+fn scheduled_tx() {
+    let mut bc = create_bc(vec![Some(simple_main), Some(schedule_main)]);
+    bc.last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_1, 0, &Vec::new(), BASE_GAS, 0);
+    let scheduler_balance = giga(2) - u128::from(BASE_GAS) - 100;
+    assert_eq!(
+        bc.last_block().account_meta_at(&ADDR_2).unwrap().balance,
+        scheduler_balance
+    );
+
+    bc.create_block();
+    assert!(bc.last_block().receipts().is_empty());
+
+    bc.create_block();
+    let receipts = bc.last_block().receipts();
+    assert_eq!(receipts.len(), 1);
+    assert_eq!(receipts[0].caller(), &ADDR_2);
+    assert_eq!(receipts[0].output(), &[1, 2, 3, 4]);
+    assert_eq!(bc.last_block().events().len(), 1);
+    assert_eq!(
+        bc.last_block().account_meta_at(&ADDR_1).unwrap().balance,
+        giga(1) + 100
+    );
+    assert!(bc.last_block().scheduled_calls.is_empty());
+}
+
+#[test]
+fn journal_replay_scheduled_tx() {
+    let buf = SharedBuf::default();
+    let mut bc = create_bc(vec![Some(simple_main), Some(schedule_main)])
+        .with_journal(Journal::new(buf.clone()));
+    bc.last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_1, 0, &[], BASE_GAS, 0);
+    bc.create_block();
+    bc.create_block();
+    let journal = buf.0.lock().unwrap().clone();
+
+    let replayed = Memchain::replay(
+        "memchain",
+        genesis_state(vec![Some(simple_main), Some(schedule_main)]),
+        BASE_GAS,
+        journal.as_slice(),
+    )
+    .unwrap();
+    assert_eq!(
+        replayed.last_block().completed_transactions,
+        bc.last_block().completed_transactions
+    );
+
+    match Memchain::replay(
+        "memchain",
+        genesis_state(vec![Some(fail_main), Some(schedule_main)]),
+        BASE_GAS,
+        journal.as_slice(),
+    ) {
+        Err(ReplayError::ReceiptMismatch {
+            height,
+            index,
+            expected,
+            actual,
+        }) => {
+            assert_eq!((height, index), (2, 0));
+            assert_eq!(expected.output, &[1, 2, 3, 4]);
+            assert!(blockchain_traits::Receipt::reverted(&*actual));
+        }
+        res => panic!("unexpected replay result: {:?}", res.map(|_| ())),
+    }
+}
+
+fn schedule_expensive_main(ptx: &mut dyn PendingTransaction) -> u16 {
+    let height = ptx.block_height() + 2;
+    let outcome = ptx.schedule(height, ADDR_1, 100 /* value */, &[], BASE_GAS * 3);
+    assert_eq!(outcome, blockchain_traits::TransactionOutcome::Success);
+    0
+}
+
+#[test]
+fn scheduled_tx_refund() {
+    let gas_price = 1;
+    let balance = |bc: &Memchain| bc.last_block().account_meta_at(&ADDR_2).unwrap().balance;
+
+    // A call that runs is charged all of its gas, and its value is refunded if it reverts.
+    let mut bc = create_bc(vec![Some(fail_main), Some(schedule_expensive_main)]);
+    bc.last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_1, 0, &[], BASE_GAS, gas_price);
+    assert_eq!(balance(&bc), giga(2) - u128::from(BASE_GAS * 3) - 100);
+    bc.create_block();
+    bc.create_block();
+    let receipts = bc.last_block().receipts();
+    assert!(receipts[0].reverted());
+    assert_eq!(receipts[0].gas_used(), BASE_GAS * 3);
+    assert_eq!(balance(&bc), giga(2) - u128::from(BASE_GAS * 3));
+
+    // A call to an account that no longer exists is charged only the base gas.
+    let mut bc = create_bc(vec![Some(fail_main), Some(schedule_expensive_main)]);
+    bc.last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_1, 0, &[], BASE_GAS, gas_price);
+    bc.blocks.last_mut().unwrap().state.remove(&ADDR_1);
+    bc.create_block();
+    bc.create_block();
+    let receipts = bc.last_block().receipts();
+    assert_eq!(
+        receipts[0].outcome(),
+        blockchain_traits::TransactionOutcome::InvalidCallee
+    );
+    assert_eq!(receipts[0].gas_used(), BASE_GAS);
+    assert_eq!(balance(&bc), giga(2) - u128::from(BASE_GAS));
+}
+
+fn confidential_main(ptx: &mut dyn PendingTransaction) -> u16 {
+    let input = ptx.input().to_vec();
+    ptx.state_mut().set(b"secret", &input);
+    ptx.emit(&[&[42u8; 32][..]], &input);
+    let mut rv = ptx.aad().to_vec();
+    rv.extend_from_slice(&input);
+    ptx.ret(&rv);
+    0
+}
+
+#[test]
+fn confidential_tx() {
+    let confidential_key = [7u8; 32];
+    let mut genesis_state = genesis_state(vec![None, Some(confidential_main)]);
+    genesis_state.get_mut(&ADDR_2).unwrap().confidential_key = Some(confidential_key);
+    let mut bc = Memchain::new("memchain", genesis_state, BASE_GAS);
+
+    let leaks = |bc: &Memchain, plaintext: &[u8]| {
+        bc.last_block().state[&ADDR_2]
+            .storage
+            .iter()
+            .flat_map(|(k, v)| vec![k, v])
+            .any(|bytes| bytes.windows(plaintext.len()).any(|w| w == plaintext))
+    };
+
+    assert!(!leaks(&bc, b"common_value"));
+    assert_eq!(
+        bc.last_block()
+            .state_at(&ADDR_2)
+            .unwrap()
+            .get(b"common_key"),
+        Some(b"common_value".to_vec())
+    );
+
+    let tx_key = confidential::tx_key(&confidential_key, &ADDR_1);
+    let input = confidential::seal(&tx_key, &[1u8; 16], b"aad", b"secret input");
+
+    let mut tampered_input = input.clone();
+    *tampered_input.last_mut().unwrap() ^= 1;
+    let receipt =
+        bc.last_block_mut()
+            .transact(ADDR_1, ADDR_2, ADDR_1, 0, &tampered_input, BASE_GAS, 0);
+    assert_eq!(
+        receipt.outcome(),
+        blockchain_traits::TransactionOutcome::InvalidInput
+    );
+
+    let receipt = bc
+        .last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_1, 0, &input, BASE_GAS, 0);
+    assert_eq!(
+        receipt.outcome(),
+        blockchain_traits::TransactionOutcome::Success
+    );
+
+    let output = confidential::open(&tx_key, receipt.output()).unwrap();
+    assert_eq!(output.aad, b"aad");
+    assert_eq!(output.plaintext, b"aadsecret input");
+
+    let events = bc.last_block().events();
+    assert_eq!(events.len(), 1);
+    let event_data = confidential::open(&tx_key, &events[0].data).unwrap();
+    assert_eq!(event_data.plaintext, b"secret input");
+
+    assert!(!leaks(&bc, b"secret input"));
+    assert_eq!(
+        bc.last_block().state_at(&ADDR_2).unwrap().get(b"secret"),
+        Some(b"secret input".to_vec())
+    );
+}
+
+const CONFIDENTIAL_KEY: confidential::Key = [7u8; 32];
+
+fn confidential_subtx_main(ptx: &mut dyn PendingTransaction) -> u16 {
+    let input = ptx.input().to_vec();
+
+    let unsealed = ptx.transact(ADDR_1, 0 /* value */, &input);
+    assert_eq!(
+        unsealed.outcome(),
+        blockchain_traits::TransactionOutcome::InvalidInput
+    );
+
+    let tx_key = confidential::tx_key(&CONFIDENTIAL_KEY, ptx.address());
+    let sealed_input = confidential::seal(&tx_key, &[2u8; 16], b"subtx aad", &input);
+    let subtx = ptx.transact(ADDR_1, 0 /* value */, &sealed_input);
+    if subtx.reverted() {
+        return 1;
+    }
+    ptx.ret(&subtx.output().to_vec());
+    0
+}
+
+fn confidential_schedule_main(ptx: &mut dyn PendingTransaction) -> u16 {
+    let tx_key = confidential::tx_key(&CONFIDENTIAL_KEY, ptx.address());
+    let sealed_input =
+        confidential::seal(&tx_key, &[3u8; 16], b"scheduled aad", &ptx.input().to_vec());
+    let height = ptx.block_height() + 1;
+    let outcome = ptx.schedule(height, ADDR_1, 0 /* value */, &sealed_input, BASE_GAS);
+    assert_eq!(outcome, blockchain_traits::TransactionOutcome::Success);
+    0
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+#[test]
+fn confidential_subtx() {
+    let mut genesis_state =
+        genesis_state(vec![Some(confidential_main), Some(confidential_subtx_main)]);
+    genesis_state.get_mut(&ADDR_1).unwrap().confidential_key = Some(CONFIDENTIAL_KEY);
+    let mut bc = Memchain::new("memchain", genesis_state, BASE_GAS);
+
+    let receipt =
+        bc.last_block_mut()
+            .transact(ADDR_1, ADDR_2, ADDR_1, 0, b"secret input", BASE_GAS * 3, 0);
+    assert_eq!(
+        receipt.outcome(),
+        blockchain_traits::TransactionOutcome::Success
+    );
+
+    // The subtransaction's output is sealed for its caller and bound to the AAD of its own
+    // input rather than that of the top-level transaction.
+    let tx_key = confidential::tx_key(&CONFIDENTIAL_KEY, &ADDR_2);
+    assert!(!contains(receipt.output(), b"secret input"));
+    let output = confidential::open(&tx_key, receipt.output()).unwrap();
+    assert_eq!(output.aad, b"subtx aad");
+    assert_eq!(output.plaintext, b"subtx aadsecret input");
+
+    let events = bc.last_block().events();
+    assert_eq!(events.len(), 1);
+    assert!(!contains(&events[0].data, b"secret input"));
+    let event_data = confidential::open(&tx_key, &events[0].data).unwrap();
+    assert_eq!(event_data.plaintext, b"secret input");
+}
+
+#[test]
+fn confidential_scheduled_tx() {
+    let mut genesis_state = genesis_state(vec![
+        Some(confidential_main),
+        Some(confidential_schedule_main),
+    ]);
+    genesis_state.get_mut(&ADDR_1).unwrap().confidential_key = Some(CONFIDENTIAL_KEY);
+    let mut bc = Memchain::new("memchain", genesis_state, BASE_GAS);
+
+    bc.last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_1, 0, b"secret input", BASE_GAS, 0);
+    bc.create_block();
+
+    let receipts = bc.last_block().receipts();
+    assert_eq!(receipts.len(), 1);
+    assert_eq!(
+        receipts[0].outcome(),
+        blockchain_traits::TransactionOutcome::Success
+    );
+
+    let tx_key = confidential::tx_key(&CONFIDENTIAL_KEY, &ADDR_2);
+    assert!(!contains(receipts[0].output(), b"secret input"));
+    let output = confidential::open(&tx_key, receipts[0].output()).unwrap();
+    assert_eq!(output.aad, b"scheduled aad");
+    assert_eq!(output.plaintext, b"scheduled aadsecret input");
+
+    let events = bc.last_block().events();
+    assert_eq!(events.len(), 1);
+    assert!(!contains(&events[0].data, b"secret input"));
+    let event_data = confidential::open(&tx_key, &events[0].data).unwrap();
+    assert_eq!(event_data.plaintext, b"secret input");
+}
+
+fn storage_main(ptx: &mut dyn PendingTransaction) -> u16 {
+    match ptx.input().to_vec().as_slice() {
+        b"set" => ptx.state_mut().set(b"key", b"value"),
+        b"remove" => ptx.state_mut().remove(b"key"),
+        b"remove_genesis" => ptx.state_mut().remove(b"common_key"),
+        _ => ptx.state_mut().set(b"key", &[0u8; 1_000_000]),
+    }
+    0
+}
+
+#[test]
+fn storage_deposit() {
+    let storage_price = 10_000;
+    let mut bc = create_bc(vec![None, Some(storage_main)]).with_storage_price(storage_price);
+    let meta = |bc: &Memchain| bc.last_block().account_meta_at(&ADDR_2).unwrap();
+    let initial_meta = meta(&bc);
+
+    let entry_size = (b"key".len() + b"value".len()) as u64;
+    bc.last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_1, 0, b"set", BASE_GAS, 0);
+    assert_eq!(
+        meta(&bc).balance,
+        initial_meta.balance - storage_price * u128::from(entry_size)
+    );
+    assert_eq!(
+        meta(&bc).storage_size,
+        initial_meta.storage_size + entry_size
+    );
+
+    let receipt = bc
+        .last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_1, 0, b"grow", BASE_GAS, 0);
+    assert_eq!(
+        receipt.outcome(),
+        blockchain_traits::TransactionOutcome::InsufficientFunds
+    );
+    assert_eq!(
+        meta(&bc).storage_size,
+        initial_meta.storage_size + entry_size
+    );
+
+    // The refund is the deposit that was paid, even if the price has changed since.
+    bc.blocks.last_mut().unwrap().storage_price = storage_price * 2;
+    bc.last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_1, 0, b"remove", BASE_GAS, 0);
+    assert_eq!(meta(&bc), initial_meta);
+
+    // Entries in the genesis state have no deposit to refund.
+    bc.last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_1, 0, b"remove_genesis", BASE_GAS, 0);
+    assert_eq!(meta(&bc).balance, initial_meta.balance);
+    assert_eq!(
+        meta(&bc).storage_size,
+        initial_meta.storage_size - (b"common_key".len() + b"common_value".len()) as u64
+    );
+}
+
+#[test]
+fn journal_replay_storage_price() {
+    let buf = SharedBuf::default();
+    let mut bc = create_bc(vec![None, Some(storage_main)])
+        .with_journal(Journal::new(buf.clone()))
+        .with_storage_price(10_000);
+    bc.last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_1, 0, b"set", BASE_GAS, 0);
+    bc.last_block_mut()
+        .transact(ADDR_1, ADDR_2, ADDR_1, 0, b"grow", BASE_GAS, 0);
+    bc.create_block();
+
+    let journal = buf.0.lock().unwrap().clone();
+    let replayed = Memchain::replay(
+        "memchain",
+        genesis_state(vec![None, Some(storage_main)]),
+        BASE_GAS,
+        journal.as_slice(),
+    )
+    .unwrap();
+    assert_eq!(replayed.storage_price, 10_000);
+    for (replayed_block, block) in replayed.blocks.iter().zip(bc.blocks.iter()) {
+        assert_eq!(replayed_block.state, block.state);
+        assert_eq!(
+            replayed_block.completed_transactions,
+            block.completed_transactions
+        );
+    }
+}
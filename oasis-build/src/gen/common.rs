@@ -29,6 +29,8 @@ pub fn quote_ty(ty: &oasis_rpc::Type) -> TokenStream {
         Type::I32 => quote!(i32),
         Type::U64 => quote!(u64),
         Type::I64 => quote!(i64),
+        Type::U128 => quote!(u128),
+        Type::I128 => quote!(i128),
         Type::F32 => quote!(f32),
         Type::F64 => quote!(f64),
         Type::Bytes => quote!(Vec<u8>),
@@ -299,8 +299,8 @@ fn convert_ty(tcx: TyCtxt, ty: &rustc_hir::Ty) -> Result<Type, UnsupportedTypeEr
                     }
                 },
                 Res::PrimTy(ty) => match ty {
-                    rustc_hir::PrimTy::Int(ty) => convert_int(ty, path.span)?,
-                    rustc_hir::PrimTy::Uint(ty) => convert_uint(ty, path.span)?,
+                    rustc_hir::PrimTy::Int(ty) => convert_int(ty),
+                    rustc_hir::PrimTy::Uint(ty) => convert_uint(ty),
                     rustc_hir::PrimTy::Float(ty) => convert_float(ty, path.span)?,
                     rustc_hir::PrimTy::Str => Type::String,
                     rustc_hir::PrimTy::Bool => Type::Bool,
@@ -343,8 +343,8 @@ fn convert_sty_with_arg_at<'tcx>(
     Ok(match ty.kind {
         Bool => Type::Bool,
         Char => Type::I8,
-        Int(ty) => convert_int(ty, tcx.def_span(did))?,
-        Uint(ty) => convert_uint(ty, tcx.def_span(did))?,
+        Int(ty) => convert_int(ty),
+        Uint(ty) => convert_uint(ty),
         Float(ty) => convert_float(ty, tcx.def_span(did))?,
         Adt(AdtDef { did, .. }, substs) => convert_def!(tcx, *did, *did, |i| arg_at(substs, i))?,
         Str => Type::String,
@@ -387,42 +387,31 @@ fn convert_sty_with_arg_at<'tcx>(
     })
 }
 
-fn convert_int(
-    ty: syntax::ast::IntTy,
-    span: rustc_span::Span,
-) -> Result<Type, UnsupportedTypeError> {
+/// `isize` is given a fixed width of 64 bits so that the interface does not depend on the
+/// target. It is encoded by borsh as an `i64`.
+fn convert_int(ty: syntax::ast::IntTy) -> Type {
     use syntax::ast::IntTy;
-    Ok(match ty {
+    match ty {
         IntTy::I8 => Type::I8,
         IntTy::I16 => Type::I16,
         IntTy::I32 => Type::I32,
-        IntTy::I64 => Type::I64,
-        IntTy::I128 | IntTy::Isize => {
-            return Err(UnsupportedTypeError {
-                type_name: ty.name_str().to_string(),
-                span,
-            })
-        }
-    })
+        IntTy::I64 | IntTy::Isize => Type::I64,
+        IntTy::I128 => Type::I128,
+    }
 }
 
-fn convert_uint(
-    ty: syntax::ast::UintTy,
-    span: rustc_span::Span,
-) -> Result<Type, UnsupportedTypeError> {
+/// `usize` is given a fixed width of 64 bits so that the interface does not depend on the
+/// target. It is encoded by borsh as a `u64`, so a service built for wasm32 rejects a
+/// `usize` argument larger than `u32::MAX`.
+fn convert_uint(ty: syntax::ast::UintTy) -> Type {
     use syntax::ast::UintTy;
-    Ok(match ty {
+    match ty {
         UintTy::U8 => Type::U8,
         UintTy::U16 => Type::U16,
         UintTy::U32 => Type::U32,
-        UintTy::U64 => Type::U64,
-        UintTy::U128 | UintTy::Usize => {
-            return Err(UnsupportedTypeError {
-                type_name: ty.name_str().to_string(),
-                span,
-            })
-        }
-    })
+        UintTy::U64 | UintTy::Usize => Type::U64,
+        UintTy::U128 => Type::U128,
+    }
 }
 
 fn convert_float(
@@ -252,13 +252,9 @@ impl Gateway for HttpGateway {
         };
 
         self.post_and_poll(SERVICE_DEPLOY_API, body)
-            .and_then(|event| {
-                match event {
-                    Event::DeployService { address, .. } => {
-                        Ok(Address::from_str(&address[2..] /* strip 0x */)?)
-                    }
-                    e => Err(anyhow!("expecting `DeployService` event. got {:?}", e)),
-                }
+            .and_then(|event| match event {
+                Event::DeployService { address, .. } => Ok(Address::from_str(&address)?),
+                e => Err(anyhow!("expecting `DeployService` event. got {:?}", e)),
             })
             .map_err(RpcError::Gateway)
     }
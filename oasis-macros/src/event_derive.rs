@@ -1,66 +1,307 @@
+/// The maximum number of fields of an event that can be `#[indexed]`.
+const MAX_INDEXED_FIELDS: usize = 3;
+
 #[proc_macro_derive(Event, attributes(indexed))]
 pub fn event_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as syn::DeriveInput);
     let event_name = &input.ident;
-    let generics = &input.generics;
+    let vis = &input.vis;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
-    let fields = match input.data {
-        syn::Data::Struct(syn::DataStruct { fields, .. }) => fields,
+    // Each event is a struct or a variant of an enum.
+    let event_defs: Vec<EventDef> = match &input.data {
+        syn::Data::Struct(syn::DataStruct { fields, .. }) => vec![EventDef {
+            name: event_name.to_string(),
+            path: quote!(Self),
+            fields,
+            filter_ident: format_ident!("{}TopicFilter", event_name),
+            filter_ctor_ident: format_ident!("topic_filter"),
+        }],
+        syn::Data::Enum(syn::DataEnum { variants, .. }) if !variants.is_empty() => variants
+            .iter()
+            .map(|variant| {
+                let variant_name = &variant.ident;
+                EventDef {
+                    name: format!("{}::{}", event_name, variant_name),
+                    path: quote!(Self::#variant_name),
+                    fields: &variant.fields,
+                    filter_ident: format_ident!("{}{}TopicFilter", event_name, variant_name),
+                    filter_ctor_ident: format_ident!(
+                        "{}_topic_filter",
+                        to_snake_case(&variant_name.to_string())
+                    ),
+                }
+            })
+            .collect(),
         _ => {
-            err!(input: "an `Event` must be a struct.");
+            err!(input: "an `Event` must be a struct or a non-empty enum.");
             return proc_macro::TokenStream::new();
         }
     };
 
-    fn is_indexed(field: &syn::Field) -> bool {
-        field.attrs.iter().any(|attr| attr.path.is_ident("indexed"))
+    let mut has_err = false;
+    for event_def in event_defs.iter() {
+        if let Some(field) = event_def.indexed_fields().nth(MAX_INDEXED_FIELDS) {
+            err!(field: "an `Event` can have at most {} indexed fields.", MAX_INDEXED_FIELDS);
+            has_err = true;
+        }
+    }
+    if has_err {
+        return proc_macro::TokenStream::new();
     }
 
-    let indexed_field_idents = match fields {
-        syn::Fields::Named(syn::FieldsNamed { named, .. }) => named
-            .iter()
-            .filter_map(|field| {
-                if is_indexed(field) {
-                    Some(syn::Member::Named(field.ident.as_ref().unwrap().clone()))
-                } else {
-                    None
-                }
-            })
-            .collect(),
-        syn::Fields::Unnamed(syn::FieldsUnnamed { unnamed, .. }) => unnamed
-            .iter()
-            .enumerate()
-            .filter_map(|(i, field)| {
-                if is_indexed(field) {
-                    Some(syn::Member::Unnamed(syn::Index {
-                        index: i as u32,
-                        span: proc_macro2::Span::call_site(),
-                    }))
-                } else {
-                    None
+    let signature_topics: Vec<proc_macro2::TokenStream> =
+        event_defs.iter().map(EventDef::signature_topic).collect();
+
+    let topics_arms = event_defs.iter().zip(signature_topics.iter()).map(|(event_def, sig)| {
+        let pat = event_def.indexed_pat();
+        let bindings = event_def.indexed_bindings();
+        quote! {
+            #pat => vec![#sig, #(encode_event_topic(#bindings)),*]
+        }
+    });
+
+    let filter_idents: Vec<_> = event_defs.iter().map(|def| &def.filter_ident).collect();
+    let filter_ctor_idents = event_defs.iter().map(|def| &def.filter_ctor_ident);
+    let filter_docs = event_defs.iter().map(|def| {
+        format!(
+            "Builds a filter that matches `{}` events by their indexed fields.",
+            def.name
+        )
+    });
+    let filter_impls = event_defs
+        .iter()
+        .map(|event_def| {
+            let filter_ident = &event_def.filter_ident;
+            let num_indexed = event_def.indexed_fields().count();
+            let (with_idents, tys): (Vec<_>, Vec<_>) = event_def
+                .indexed_fields()
+                .enumerate()
+                .map(|(i, field)| {
+                    let with_ident = match &field.ident {
+                        Some(ident) => format_ident!("with_{}", ident),
+                        None => format_ident!("with_{}", event_def.member_index(field)),
+                    };
+                    (with_ident, (i + 1, &field.ty))
+                })
+                .unzip();
+            let (topic_idxs, tys): (Vec<_>, Vec<_>) = tys.into_iter().unzip();
+            quote! {
+                impl #impl_generics #filter_ident #ty_generics #where_clause {
+                    #(
+                        #vis fn #with_idents(mut self, value: &#tys) -> Self {
+                            self.filter = self
+                                .filter
+                                .with_topic(#topic_idxs, encode_event_topic(value));
+                            self
+                        }
+                    )*
+
+                    /// Returns whether `event` is of this type and has the required indexed
+                    /// fields.
+                    #vis fn matches(&self, event: &RawEvent) -> bool {
+                        event.topics.len() == 1 + #num_indexed && self.filter.matches(event)
+                    }
+
+                    #vis fn into_inner(self) -> TopicFilter {
+                        self.filter
+                    }
                 }
-            })
-            .collect(),
-        syn::Fields::Unit => Vec::new(),
-    };
+            }
+        })
+        .collect::<Vec<_>>();
 
     let impl_wrapper_ident = format_ident!("_IMPL_EVENT_FOR_{}", event_name);
 
     proc_macro::TokenStream::from(quote! {
+        #(
+            #[doc = #filter_docs]
+            #[derive(Clone, Debug)]
+            #vis struct #filter_idents #impl_generics #where_clause {
+                filter: oasis_std::exe::TopicFilter,
+                _event: std::marker::PhantomData<fn() -> #event_name #ty_generics>,
+            }
+        )*
+
         #[allow(non_upper_case_globals)]
         const #impl_wrapper_ident: () = {
-            use oasis_std::{abi::*, exe::{encode_event_topic, Event}};
-
-            impl#generics Event for #event_name#generics  {
-                fn emit(&self) {
-                    let topics: &[[u8; 32]] = &[
-                        encode_event_topic(&stringify!(#event_name)),
-                        #(encode_event_topic(&self.#indexed_field_idents)),*
-                    ];
-                    let topic_refs: Vec<&[u8]> = topics.iter().map(|t| t.as_ref()).collect();
-                    oasis_std::backend::emit(&topic_refs, &self.try_to_vec().unwrap());
+            use oasis_std::{
+                abi::*,
+                exe::{encode_event_topic, Event, RawEvent, TopicFilter},
+            };
+
+            impl #impl_generics Event for #event_name #ty_generics #where_clause {
+                fn topics(&self) -> Vec<[u8; 32]> {
+                    match self {
+                        #(#topics_arms),*
+                    }
+                }
+
+                fn try_decode(event: &RawEvent) -> Option<Self> {
+                    let signature_topic = event.topics.first()?;
+                    if ![#(#signature_topics),*].contains(signature_topic) {
+                        return None;
+                    }
+                    let decoded = Self::try_from_slice(&event.data).ok()?;
+                    if decoded.topics() != event.topics {
+                        return None;
+                    }
+                    Some(decoded)
                 }
             }
+
+            impl #impl_generics #event_name #ty_generics #where_clause {
+                #(
+                    /// Returns a filter that matches every event of this type.
+                    #vis fn #filter_ctor_idents() -> #filter_idents #ty_generics {
+                        #filter_idents {
+                            filter: TopicFilter::default().with_topic(0, #signature_topics),
+                            _event: std::marker::PhantomData,
+                        }
+                    }
+                )*
+            }
+
+            #(#filter_impls)*
         };
     })
 }
+
+/// An event declared as a struct or as a variant of an enum.
+struct EventDef<'a> {
+    /// The name of the event, e.g. `Transfer` or `Token::Transfer`.
+    name: String,
+
+    /// The path used to destructure the event, e.g. `Self` or `Self::Transfer`.
+    path: proc_macro2::TokenStream,
+
+    fields: &'a syn::Fields,
+
+    filter_ident: syn::Ident,
+    filter_ctor_ident: syn::Ident,
+}
+
+impl<'a> EventDef<'a> {
+    fn indexed_fields(&self) -> impl Iterator<Item = &'a syn::Field> {
+        let fields: &'a syn::Fields = self.fields;
+        fields
+            .iter()
+            .filter(|field| field.attrs.iter().any(|attr| attr.path.is_ident("indexed")))
+    }
+
+    fn member_index(&self, field: &syn::Field) -> usize {
+        self.fields
+            .iter()
+            .position(|f| std::ptr::eq(f, field))
+            .unwrap()
+    }
+
+    fn indexed_members(&self) -> Vec<syn::Member> {
+        self.indexed_fields()
+            .map(|field| match &field.ident {
+                Some(ident) => syn::Member::Named(ident.clone()),
+                None => syn::Member::Unnamed(syn::Index {
+                    index: self.member_index(field) as u32,
+                    span: proc_macro2::Span::call_site(),
+                }),
+            })
+            .collect()
+    }
+
+    fn indexed_bindings(&self) -> Vec<syn::Ident> {
+        self.indexed_members()
+            .iter()
+            .map(|member| match member {
+                syn::Member::Named(ident) => format_ident!("__{}", ident),
+                syn::Member::Unnamed(idx) => format_ident!("__{}", idx.index),
+            })
+            .collect()
+    }
+
+    /// Returns a pattern that binds the indexed fields of the event to `indexed_bindings`.
+    fn indexed_pat(&self) -> proc_macro2::TokenStream {
+        let path = &self.path;
+        let members = self.indexed_members();
+        let bindings = self.indexed_bindings();
+        quote!(#path { #(#members: #bindings,)* .. })
+    }
+
+    /// Returns the keccak256 hash of the event's signature, which is its name followed by
+    /// the types of its fields, e.g. `Transfer(Address,Address,u64)`. See `type_signature`
+    /// for how the types are written.
+    fn signature_topic(&self) -> proc_macro2::TokenStream {
+        let field_tys: Vec<String> = self
+            .fields
+            .iter()
+            .map(|field| type_signature(&field.ty))
+            .collect();
+        let signature = format!("{}({})", self.name, field_tys.join(","));
+        let topic = tiny_keccak::keccak256(signature.as_bytes());
+        let topic_bytes = topic.iter();
+        quote!([#(#topic_bytes),*])
+    }
+}
+
+/// Returns the name of `ty` in an event signature. A path is reduced to its last segment, so
+/// `oasis_std::Address` is written as `Address`, and references and lifetimes are dropped.
+/// The macro can't see through type aliases or type parameters, so those are written by name:
+/// the signature of `Deposit<T> { amount: T }` is `Deposit(T)` for every `T`.
+fn type_signature(ty: &syn::Type) -> String {
+    match ty {
+        syn::Type::Path(syn::TypePath { path, .. }) => match path.segments.last() {
+            Some(segment) => match &segment.arguments {
+                syn::PathArguments::AngleBracketed(args) => {
+                    let args: Vec<String> = args
+                        .args
+                        .iter()
+                        .filter_map(|arg| match arg {
+                            syn::GenericArgument::Lifetime(_) => None,
+                            syn::GenericArgument::Type(ty) => Some(type_signature(ty)),
+                            arg => Some(strip_whitespace(quote!(#arg))),
+                        })
+                        .collect();
+                    format!("{}<{}>", segment.ident, args.join(","))
+                }
+                _ => segment.ident.to_string(),
+            },
+            None => strip_whitespace(quote!(#ty)),
+        },
+        syn::Type::Reference(syn::TypeReference { elem, .. })
+        | syn::Type::Paren(syn::TypeParen { elem, .. })
+        | syn::Type::Group(syn::TypeGroup { elem, .. }) => type_signature(elem),
+        syn::Type::Tuple(syn::TypeTuple { elems, .. }) => {
+            let elems: Vec<String> = elems.iter().map(type_signature).collect();
+            format!("({})", elems.join(","))
+        }
+        syn::Type::Slice(syn::TypeSlice { elem, .. }) => format!("[{}]", type_signature(elem)),
+        syn::Type::Array(syn::TypeArray { elem, len, .. }) => format!(
+            "[{};{}]",
+            type_signature(elem),
+            strip_whitespace(quote!(#len))
+        ),
+        _ => strip_whitespace(quote!(#ty)),
+    }
+}
+
+fn strip_whitespace(tokens: proc_macro2::TokenStream) -> String {
+    tokens
+        .to_string()
+        .chars()
+        .filter(|ch| !ch.is_whitespace())
+        .collect()
+}
+
+fn to_snake_case(camel_case: &str) -> String {
+    let mut snake_case = String::with_capacity(camel_case.len());
+    for (i, ch) in camel_case.chars().enumerate() {
+        if ch.is_uppercase() {
+            if i > 0 {
+                snake_case.push('_');
+            }
+            snake_case.extend(ch.to_lowercase());
+        } else {
+            snake_case.push(ch);
+        }
+    }
+    snake_case
+}
@@ -116,6 +116,8 @@ pub enum Type {
     I32,
     U64,
     I64,
+    U128,
+    I128,
     F32,
     F64,
     Bytes,
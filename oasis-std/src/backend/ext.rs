@@ -1,4 +1,4 @@
-use oasis_types::{Address, Balance, ExtStatusCode, RpcError};
+use oasis_types::{Address, Balance, ErrorCode, ExtStatusCode, RpcError};
 
 /// @see the `blockchain-traits` crate for descriptions of these methods.
 extern "C" {
@@ -34,6 +34,18 @@ extern "C" {
         input_len: u32,
     ) -> u32;
 
+    #[allow(improper_ctypes)]
+    pub fn oasis_schedule(
+        height: u64,
+        callee: *const Address,
+        value: *const u128,
+        input: *const u8,
+        input_len: u32,
+        gas: u64,
+    ) -> u32;
+
+    pub fn oasis_block_height(height: *mut u64) -> u32;
+
     pub fn oasis_address(addr: *mut Address) -> u32;
     pub fn oasis_sender(addr: *mut Address) -> u32;
     pub fn oasis_payer(addr: *mut Address) -> u32;
@@ -54,13 +66,14 @@ extern "C" {
 }
 
 fn unpack_rpc_error(status: u32) -> RpcError {
-    match ExtStatusCode::from_u32(status) {
-        Some(ExtStatusCode::Success) => unreachable!(),
-        Some(ExtStatusCode::InsufficientFunds) => RpcError::InsufficientFunds,
-        Some(ExtStatusCode::InvalidInput) => RpcError::InvalidInput,
-        Some(ExtStatusCode::NoAccount) => RpcError::InvalidCallee,
-        Some(_) | None => RpcError::Execution(fetch_err()),
-    }
+    let code = ExtStatusCode::from_u32(status)
+        .map(ErrorCode::from)
+        .unwrap_or(ErrorCode::Execution);
+    let output = match code {
+        ErrorCode::Execution => fetch_err(),
+        _ => Vec::new(),
+    };
+    RpcError::from_code(code, output).unwrap()
 }
 
 macro_rules! ext {
@@ -187,6 +200,34 @@ pub fn transact(callee: &Address, value: Balance, input: &[u8]) -> Result<Vec<u8
         .map_err(unpack_rpc_error)
 }
 
+pub fn schedule(
+    height: u64,
+    callee: &Address,
+    value: Balance,
+    input: &[u8],
+    gas: u64,
+) -> Result<(), RpcError> {
+    ext!(oasis_schedule(
+        height,
+        callee as *const _,
+        &value.0 as *const u128,
+        input.as_ptr(),
+        if input.len() > u32::max_value() as usize {
+            return Err(RpcError::InvalidInput);
+        } else {
+            input.len() as u32
+        },
+        gas,
+    ))
+    .map_err(unpack_rpc_error)
+}
+
+pub fn block_height() -> u64 {
+    let mut height = 0u64;
+    ext!(oasis_block_height(&mut height as *mut _)).unwrap();
+    height
+}
+
 pub fn input() -> Vec<u8> {
     let mut input_len = 0u32;
     ext!(oasis_input_len(&mut input_len as *mut _)).unwrap();
@@ -9,6 +9,6 @@ cfg_if::cfg_if! {
 }
 
 pub use imp::{
-    aad, address, balance, code, create, emit, err, input, payer, read, ret, sender, transact,
-    value, write,
+    aad, address, balance, block_height, code, create, emit, err, input, payer, read, ret,
+    schedule, sender, transact, value, write,
 };
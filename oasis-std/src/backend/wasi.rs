@@ -6,7 +6,7 @@ use std::{
     str::FromStr,
 };
 
-use oasis_types::{Address, Balance, RpcError};
+use oasis_types::{env, Address, Balance, ErrorCode, RpcError};
 use wasi::wasi_unstable::raw::{__wasi_errno_t, __wasi_fd_t};
 
 #[link(wasm_import_module = "wasi_unstable")]
@@ -29,6 +29,20 @@ extern "C" {
         input_len: u64,
         fd: *mut __wasi_fd_t,
     ) -> __wasi_errno_t;
+
+    #[link_name = "blockchain_schedule"]
+    #[allow(improper_ctypes)]
+    fn __wasi_blockchain_schedule(
+        height: u64,
+        callee_addr: *const u8,
+        value: *const u128,
+        input: *const u8,
+        input_len: u64,
+        gas: u64,
+    ) -> __wasi_errno_t;
+
+    #[link_name = "blockchain_height"]
+    fn __wasi_blockchain_height(height: *mut u64) -> __wasi_errno_t;
 }
 
 macro_rules! chain_dir {
@@ -52,23 +66,23 @@ fn env_addr(key: &str) -> Address {
 }
 
 pub fn address() -> Address {
-    env_addr("ADDRESS")
+    env_addr(env::ADDRESS)
 }
 
 pub fn sender() -> Address {
-    env_addr("SENDER")
+    env_addr(env::SENDER)
 }
 
 pub fn payer() -> Address {
-    env_addr("PAYER")
+    env_addr(env::PAYER)
 }
 
 pub fn aad() -> Vec<u8> {
-    base64::decode(&std::env::var_os("AAD").unwrap().into_vec()).unwrap()
+    base64::decode(&std::env::var_os(env::AAD).unwrap().into_vec()).unwrap()
 }
 
 pub fn value() -> Balance {
-    Balance(u128::from_str(&std::env::var("VALUE").unwrap()).unwrap())
+    Balance(u128::from_str(&std::env::var(env::VALUE).unwrap()).unwrap())
 }
 
 pub fn balance(addr: &Address) -> Option<Balance> {
@@ -106,23 +120,13 @@ pub fn create(value: Balance, code: &[u8]) -> Result<Address, RpcError> {
     f_out
         .read_to_end(&mut out)
         .unwrap_or_else(|err| panic!(err));
-    use wasi::wasi_unstable::raw::*;
-    match errno {
-        __WASI_ESUCCESS => {
-            if out.len() != Address::size() {
-                Err(RpcError::InvalidOutput(out))
-            } else {
-                let mut addr = Address::default();
-                addr.0.copy_from_slice(&out);
-                Ok(addr)
-            }
-        }
-        __WASI_EFAULT | __WASI_EINVAL => Err(RpcError::InvalidInput),
-        __WASI_ENOENT => Err(RpcError::InvalidCallee),
-        __WASI_EDQUOT => Err(RpcError::InsufficientFunds),
-        __WASI_ECONNABORTED => Err(RpcError::Execution(out)),
-        _ => unreachable!(),
+    let out = call_result(errno, out)?;
+    if out.len() != Address::size() {
+        return Err(RpcError::InvalidOutput(out));
     }
+    let mut addr = Address::default();
+    addr.0.copy_from_slice(&out);
+    Ok(addr)
 }
 
 pub fn transact(callee: &Address, value: Balance, input: &[u8]) -> Result<Vec<u8>, RpcError> {
@@ -141,17 +145,45 @@ pub fn transact(callee: &Address, value: Balance, input: &[u8]) -> Result<Vec<u8
     f_out
         .read_to_end(&mut out)
         .unwrap_or_else(|err| panic!(err));
-    use wasi::wasi_unstable::raw::*;
-    match errno {
-        __WASI_ESUCCESS => Ok(out),
-        __WASI_EFAULT | __WASI_EINVAL => Err(RpcError::InvalidInput),
-        __WASI_ENOENT => Err(RpcError::InvalidCallee),
-        __WASI_EDQUOT => Err(RpcError::InsufficientFunds),
-        __WASI_ECONNABORTED => Err(RpcError::Execution(out)),
-        _ => unreachable!(),
+    call_result(errno, out)
+}
+
+pub fn schedule(
+    height: u64,
+    callee: &Address,
+    value: Balance,
+    input: &[u8],
+    gas: u64,
+) -> Result<(), RpcError> {
+    let errno = unsafe {
+        __wasi_blockchain_schedule(
+            height,
+            callee.0.as_ptr(),
+            &value.0 as *const u128,
+            input.as_ptr(),
+            input.len() as u64,
+            gas,
+        )
+    };
+    call_result(errno, Vec::new()).map(drop)
+}
+
+/// Returns the result of a call whose outcome the host reported as `errno`.
+fn call_result(errno: __wasi_errno_t, output: Vec<u8>) -> Result<Vec<u8>, RpcError> {
+    match ErrorCode::from_errno(errno) {
+        Some(ErrorCode::Success) => Ok(output),
+        Some(code) => Err(RpcError::from_code(code, output).unwrap_or(RpcError::Fatal)),
+        None => Err(RpcError::Fatal), // The host failed to report the outcome.
     }
 }
 
+pub fn block_height() -> u64 {
+    let mut height = 0u64;
+    let errno = unsafe { __wasi_blockchain_height(&mut height as *mut _) };
+    assert_eq!(errno, wasi::wasi_unstable::raw::__WASI_ESUCCESS);
+    height
+}
+
 pub fn input() -> Vec<u8> {
     let mut inp = Vec::new();
     io::stdin().read_to_end(&mut inp).unwrap();
@@ -0,0 +1,156 @@
+//! Hashes, signature verification and key recovery.
+//!
+//! On the Oasis platform, these are computed by the host. When testing, they're computed by
+//! pure-Rust implementations that give identical results.
+//!
+//! secp256k1 public keys are 64 bytes: the uncompressed point without its `0x04` prefix.
+//! secp256k1 signatures are `r || s`, optionally followed by a recovery id of 0 or 1 (or 27
+//! or 28, as is common in Ethereum).
+
+use oasis_types::Address;
+
+cfg_if::cfg_if! {
+    if #[cfg(all(target_arch = "wasm32", target_os = "wasi"))] {
+        mod wasi;
+        use self::wasi as imp;
+    } else {
+        mod native;
+        use native as imp;
+    }
+}
+
+/// Returns the keccak256 digest of `data`.
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    imp::keccak256(data)
+}
+
+/// Returns the SHA-256 digest of `data`.
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    imp::sha256(data)
+}
+
+/// Returns whether `signature` is a valid ed25519 signature of `message` by `public_key`.
+pub fn ed25519_verify(public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+    imp::ed25519_verify(public_key, message, signature)
+}
+
+/// Returns whether `signature` is a valid secp256k1 signature of `message_hash` by
+/// `public_key`.
+pub fn secp256k1_verify(
+    public_key: &[u8; 64],
+    message_hash: &[u8; 32],
+    signature: &[u8; 64],
+) -> bool {
+    imp::secp256k1_verify(public_key, message_hash, signature)
+}
+
+/// Returns the public key that produced the recoverable `signature` of `message_hash`, or
+/// `None` if the signature is invalid.
+pub fn secp256k1_recover(message_hash: &[u8; 32], signature: &[u8; 65]) -> Option<[u8; 64]> {
+    let recovery_id = match signature[64] {
+        id @ 0..=1 => id,
+        id @ 27..=28 => id - 27,
+        _ => return None,
+    };
+    let mut rs = [0u8; 64];
+    rs.copy_from_slice(&signature[..64]);
+    imp::secp256k1_recover(message_hash, &rs, recovery_id)
+}
+
+/// Returns the address of the account that owns the secp256k1 `public_key`, which is the
+/// last 20 bytes of its keccak256 digest.
+pub fn address_from_public_key(public_key: &[u8; 64]) -> Address {
+    let mut addr = Address::default();
+    addr.0.copy_from_slice(&keccak256(public_key)[12..]);
+    addr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex<T: Default + AsMut<[u8]>>(hex_str: &str) -> T {
+        let mut bytes = T::default();
+        bytes
+            .as_mut()
+            .copy_from_slice(&hex::decode(hex_str).unwrap());
+        bytes
+    }
+
+    #[test]
+    fn test_hashes() {
+        assert_eq!(
+            keccak256(b""),
+            from_hex::<[u8; 32]>(
+                "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+            )
+        );
+        assert_eq!(
+            sha256(b"abc"),
+            from_hex::<[u8; 32]>(
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+            )
+        );
+    }
+
+    #[test]
+    fn test_ed25519() {
+        // Test 1 from RFC 8032.
+        let public_key =
+            from_hex("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a");
+        let mut signature = [0u8; 64];
+        signature.copy_from_slice(
+            &hex::decode(
+                "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+            )
+            .unwrap(),
+        );
+        assert!(ed25519_verify(&public_key, b"", &signature));
+        assert!(!ed25519_verify(&public_key, b"x", &signature));
+        signature[0] ^= 1;
+        assert!(!ed25519_verify(&public_key, b"", &signature));
+    }
+
+    #[test]
+    fn test_secp256k1() {
+        let mut secret_key = [0u8; 32];
+        secret_key[31] = 1;
+        let secret_key = secp256k1::SecretKey::parse(&secret_key).unwrap();
+        let mut public_key = [0u8; 64];
+        public_key
+            .copy_from_slice(&secp256k1::PublicKey::from_secret_key(&secret_key).serialize()[1..]);
+
+        let message_hash = keccak256(b"message");
+        let (sig, recovery_id) =
+            secp256k1::sign(&secp256k1::Message::parse(&message_hash), &secret_key);
+        let signature = sig.serialize();
+        assert!(secp256k1_verify(&public_key, &message_hash, &signature));
+        assert!(!secp256k1_verify(
+            &public_key,
+            &keccak256(b"other"),
+            &signature
+        ));
+
+        let mut recoverable_signature = [0u8; 65];
+        recoverable_signature[..64].copy_from_slice(&signature);
+        recoverable_signature[64] = recovery_id.serialize();
+        assert_eq!(
+            secp256k1_recover(&message_hash, &recoverable_signature).map(|pk| pk.to_vec()),
+            Some(public_key.to_vec())
+        );
+        recoverable_signature[64] += 27;
+        assert_eq!(
+            secp256k1_recover(&message_hash, &recoverable_signature).map(|pk| pk.to_vec()),
+            Some(public_key.to_vec())
+        );
+        recoverable_signature[64] = 2;
+        assert!(secp256k1_recover(&message_hash, &recoverable_signature).is_none());
+
+        assert_eq!(
+            address_from_public_key(&public_key),
+            "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf"
+                .parse::<Address>()
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,66 @@
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    tiny_keccak::keccak256(data)
+}
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    use sha2::Digest as _;
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(&sha2::Sha256::digest(data));
+    digest
+}
+
+pub fn ed25519_verify(public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+    use ed25519_dalek::Verifier as _;
+    use std::convert::TryFrom as _;
+
+    let public_key = match ed25519_dalek::PublicKey::from_bytes(public_key) {
+        Ok(public_key) => public_key,
+        Err(_) => return false,
+    };
+    let signature = match ed25519_dalek::Signature::try_from(&signature[..]) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    public_key.verify(message, &signature).is_ok()
+}
+
+pub fn secp256k1_verify(
+    public_key: &[u8; 64],
+    message_hash: &[u8; 32],
+    signature: &[u8; 64],
+) -> bool {
+    let public_key = match secp256k1::PublicKey::parse_slice(
+        public_key,
+        Some(secp256k1::PublicKeyFormat::Raw),
+    ) {
+        Ok(public_key) => public_key,
+        Err(_) => return false,
+    };
+    let signature = match secp256k1::Signature::parse_slice(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    secp256k1::verify(
+        &secp256k1::Message::parse(message_hash),
+        &signature,
+        &public_key,
+    )
+}
+
+pub fn secp256k1_recover(
+    message_hash: &[u8; 32],
+    signature: &[u8; 64],
+    recovery_id: u8,
+) -> Option<[u8; 64]> {
+    let signature = secp256k1::Signature::parse_slice(signature).ok()?;
+    let recovery_id = secp256k1::RecoveryId::parse(recovery_id).ok()?;
+    let public_key = secp256k1::recover(
+        &secp256k1::Message::parse(message_hash),
+        &signature,
+        &recovery_id,
+    )
+    .ok()?;
+    let mut raw_public_key = [0u8; 64];
+    raw_public_key.copy_from_slice(&public_key.serialize()[1..]);
+    Some(raw_public_key)
+}
@@ -0,0 +1,104 @@
+use wasi::wasi_unstable::raw::{__wasi_errno_t, __WASI_ESUCCESS};
+
+#[link(wasm_import_module = "wasi_unstable")]
+extern "C" {
+    #[link_name = "crypto_keccak256"]
+    fn __wasi_crypto_keccak256(data: *const u8, data_len: u64, digest: *mut u8) -> __wasi_errno_t;
+
+    #[link_name = "crypto_sha256"]
+    fn __wasi_crypto_sha256(data: *const u8, data_len: u64, digest: *mut u8) -> __wasi_errno_t;
+
+    /// Returns `EINVAL` if the signature is invalid.
+    #[link_name = "crypto_ed25519_verify"]
+    fn __wasi_crypto_ed25519_verify(
+        public_key: *const u8,
+        message: *const u8,
+        message_len: u64,
+        signature: *const u8,
+    ) -> __wasi_errno_t;
+
+    /// Returns `EINVAL` if the signature is invalid.
+    #[link_name = "crypto_secp256k1_verify"]
+    fn __wasi_crypto_secp256k1_verify(
+        public_key: *const u8,
+        message_hash: *const u8,
+        signature: *const u8,
+    ) -> __wasi_errno_t;
+
+    /// Returns `EINVAL` if no public key can be recovered from the signature.
+    #[link_name = "crypto_secp256k1_recover"]
+    fn __wasi_crypto_secp256k1_recover(
+        message_hash: *const u8,
+        signature: *const u8,
+        recovery_id: u8,
+        public_key: *mut u8,
+    ) -> __wasi_errno_t;
+}
+
+/// Returns whether the host accepted the signature. Any error, including one that the host
+/// isn't expected to return, is treated as a rejection.
+fn is_valid(errno: __wasi_errno_t) -> bool {
+    errno == __WASI_ESUCCESS
+}
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut digest = [0u8; 32];
+    let errno =
+        unsafe { __wasi_crypto_keccak256(data.as_ptr(), data.len() as u64, digest.as_mut_ptr()) };
+    assert_eq!(errno, __WASI_ESUCCESS);
+    digest
+}
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut digest = [0u8; 32];
+    let errno =
+        unsafe { __wasi_crypto_sha256(data.as_ptr(), data.len() as u64, digest.as_mut_ptr()) };
+    assert_eq!(errno, __WASI_ESUCCESS);
+    digest
+}
+
+pub fn ed25519_verify(public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+    is_valid(unsafe {
+        __wasi_crypto_ed25519_verify(
+            public_key.as_ptr(),
+            message.as_ptr(),
+            message.len() as u64,
+            signature.as_ptr(),
+        )
+    })
+}
+
+pub fn secp256k1_verify(
+    public_key: &[u8; 64],
+    message_hash: &[u8; 32],
+    signature: &[u8; 64],
+) -> bool {
+    is_valid(unsafe {
+        __wasi_crypto_secp256k1_verify(
+            public_key.as_ptr(),
+            message_hash.as_ptr(),
+            signature.as_ptr(),
+        )
+    })
+}
+
+pub fn secp256k1_recover(
+    message_hash: &[u8; 32],
+    signature: &[u8; 64],
+    recovery_id: u8,
+) -> Option<[u8; 64]> {
+    let mut public_key = [0u8; 64];
+    let errno = unsafe {
+        __wasi_crypto_secp256k1_recover(
+            message_hash.as_ptr(),
+            signature.as_ptr(),
+            recovery_id,
+            public_key.as_mut_ptr(),
+        )
+    };
+    if is_valid(errno) {
+        Some(public_key)
+    } else {
+        None
+    }
+}
@@ -13,16 +13,100 @@ pub trait Service {
     }
 }
 
+/// An event as it's recorded by the blockchain.
+pub use oasis_types::Event as RawEvent;
+
+/// An event that a service can emit. It's usually derived with `#[derive(Event)]`, which
+/// makes each field marked `#[indexed]` a topic by which the event can be filtered.
+///
+/// ```
+/// use oasis_std::{abi::*, Address, Event};
+///
+/// #[derive(Serialize, Deserialize, Event)]
+/// pub struct Transfer {
+///     #[indexed]
+///     from: Address,
+///     #[indexed]
+///     to: Address,
+///     #[indexed]
+///     memo: String,
+///     amount: u64,
+/// }
+/// ```
+///
+/// An event can have at most three indexed fields.
+///
+/// ```compile_fail
+/// use oasis_std::{abi::*, Address, Event};
+///
+/// #[derive(Serialize, Deserialize, Event)]
+/// pub struct Transfer {
+///     #[indexed]
+///     from: Address,
+///     #[indexed]
+///     to: Address,
+///     #[indexed]
+///     memo: String,
+///     #[indexed]
+///     amount: u64,
+/// }
+/// ```
 pub trait Event: crate::abi::Serialize {
-    /// Emits the ABI-encoded event with the event name and indexed fields as topics.
-    /// Topics are ABI-encoded and then keccak256 hashed if longer than `TOPIC_LEN`.
-    /// Currently the maximum topic length is 32.
-    fn emit(&self);
+    /// Returns the topics of this event. The first topic is the keccak256 hash of the event's
+    /// signature, which is its name and the types of its fields, e.g.
+    /// `Transfer(Address,Address,u64)` or, for a variant of an enum, `Token::Transfer(..)`.
+    /// Type paths are reduced to their last segment, and type aliases and type parameters
+    /// are written by name.
+    /// It is followed by the indexed fields, which are ABI-encoded and then keccak256 hashed
+    /// if longer than `TOPIC_LEN`. Currently the maximum topic length is 32.
+    fn topics(&self) -> Vec<[u8; TOPIC_LEN]>;
+
+    /// Emits the ABI-encoded event with its `topics`.
+    fn emit(&self) {
+        let topics = self.topics();
+        let topic_refs: Vec<&[u8]> = topics.iter().map(|t| t.as_ref()).collect();
+        crate::backend::emit(&topic_refs, &self.try_to_vec().unwrap());
+    }
+
+    /// Decodes `event` if it was emitted as this type of event.
+    /// Returns `None` if it has different topics or its data can't be decoded.
+    fn try_decode(event: &RawEvent) -> Option<Self>
+    where
+        Self: Sized;
 }
 
 /// The maximum length of a topic. Topics longer than this will be keccak256 hashed.
 const TOPIC_LEN: usize = 32;
 
+/// Matches events by their topics. `#[derive(Event)]` generates a typed builder of
+/// `TopicFilter`s for each event, so this rarely needs to be created directly.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TopicFilter {
+    /// The topics that must be present at each position. `None` matches any topic.
+    pub topics: Vec<Option<[u8; TOPIC_LEN]>>,
+}
+
+impl TopicFilter {
+    /// Requires the topic at `index` to be `topic`.
+    pub fn with_topic(mut self, index: usize, topic: [u8; TOPIC_LEN]) -> Self {
+        if self.topics.len() <= index {
+            self.topics.resize(index + 1, None);
+        }
+        self.topics[index] = Some(topic);
+        self
+    }
+
+    /// Returns whether `event` has every topic required by this filter.
+    pub fn matches(&self, event: &RawEvent) -> bool {
+        self.topics.len() <= event.topics.len()
+            && self
+                .topics
+                .iter()
+                .zip(event.topics.iter())
+                .all(|(expected, topic)| expected.map(|t| t == *topic).unwrap_or(true))
+    }
+}
+
 /// ABI-encodes a topic and hashes it if its representation is longer than `TOPIC_LEN`.
 #[doc(hidden)]
 pub fn encode_event_topic<T: crate::abi::Serialize>(topic: &T) -> [u8; TOPIC_LEN] {
@@ -32,7 +116,7 @@ pub fn encode_event_topic<T: crate::abi::Serialize>(topic: &T) -> [u8; TOPIC_LEN
         topic[..repr.len()].copy_from_slice(&repr);
         topic
     } else {
-        tiny_keccak::keccak256(&repr)
+        crate::crypto::keccak256(&repr)
     }
 }
 
@@ -77,6 +161,11 @@ impl Context {
     pub fn value(&self) -> Balance {
         self.value.unwrap_or_else(crate::backend::value)
     }
+
+    /// Returns the height of the block in which the current RPC is executing.
+    pub fn block_height(&self) -> u64 {
+        crate::backend::block_height()
+    }
 }
 
 impl Context {
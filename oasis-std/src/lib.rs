@@ -14,6 +14,7 @@ extern crate oasis_macros;
 
 pub mod backend;
 pub mod collections;
+pub mod crypto;
 pub mod exe;
 
 pub mod abi {
@@ -82,6 +83,11 @@ macro_rules! service {
 pub trait AddressExt {
     fn call(&self, ctx: &Context, payload: &[u8]) -> Result<Vec<u8>, RpcError>;
 
+    /// Schedules an RPC to this address that will be made at the start of the block at
+    /// `height`. The value and gas of the call are taken from `ctx` and are paid by the
+    /// current service when the call is scheduled. The gas must be set using `Context::with_gas`.
+    fn schedule(&self, ctx: &Context, height: u64, payload: &[u8]) -> Result<(), RpcError>;
+
     fn transfer<B: Into<Balance>>(&self, value: B) -> Result<(), RpcError>;
 
     fn balance(&self) -> Balance;
@@ -94,6 +100,11 @@ impl AddressExt for Address {
         crate::backend::transact(self, ctx.value(), payload)
     }
 
+    fn schedule(&self, ctx: &Context, height: u64, payload: &[u8]) -> Result<(), RpcError> {
+        let gas = ctx.gas.ok_or(RpcError::InsufficientGas)?;
+        crate::backend::schedule(height, self, ctx.value(), payload, gas)
+    }
+
     fn transfer<B: Into<Balance>>(&self, value: B) -> Result<(), RpcError> {
         crate::backend::transact(self, value.into(), &[]).map(|_| ())
     }
@@ -85,6 +85,23 @@ pub extern "C" fn oasis_transact(
     ExtStatusCode::Success
 }
 
+#[no_mangle]
+pub extern "C" fn oasis_schedule(
+    height: u64,
+    callee: *const Address,
+    value: *const u128,
+    input: *const u8,
+    input_len: u32,
+    gas: u64,
+) -> ExtStatusCode {
+    ExtStatusCode::Success
+}
+
+#[no_mangle]
+pub extern "C" fn oasis_block_height(height: *mut u64) -> ExtStatusCode {
+    ExtStatusCode::Success
+}
+
 #[no_mangle]
 pub extern "C" fn oasis_address(addr: *mut Address) -> ExtStatusCode {
     ExtStatusCode::Success
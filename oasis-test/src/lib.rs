@@ -1,6 +1,9 @@
 mod ext;
 
-use std::cell::RefCell;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex, PoisonError,
+};
 
 use blockchain_traits::Blockchain as _;
 use memchain::Memchain;
@@ -9,36 +12,36 @@ use oasis_types::Address;
 const SEED_ADDR: Address = Address([0xffu8; 20]);
 const BASE_GAS: u64 = 2100;
 
-thread_local! {
-    static MEMCHAIN: RefCell<Memchain<'static>> =
-        RefCell::new(Memchain::new("testnet".to_string(), {
-            let mut genesis_state = std::collections::HashMap::new();
-            genesis_state.insert(SEED_ADDR, std::borrow::Cow::Owned(memchain::Account {
-                balance: u128::max_value(),
-                ..Default::default()
-            }));
-            genesis_state
-        }, BASE_GAS));
-    static NEXT_ADDR: RefCell<u64> = RefCell::new(0);
+lazy_static::lazy_static! {
+    /// The chain shared by all tests in the process.
+    static ref MEMCHAIN: Mutex<Memchain> = Mutex::new(Memchain::new("testnet", {
+        let mut genesis_state = std::collections::HashMap::new();
+        genesis_state.insert(SEED_ADDR, memchain::Account {
+            balance: u128::max_value(),
+            ..Default::default()
+        });
+        genesis_state
+    }, BASE_GAS));
+}
+
+static NEXT_ADDR: AtomicU64 = AtomicU64::new(0);
+
+/// Runs `f` with exclusive access to the shared test chain.
+/// A test that panics while holding the chain doesn't prevent other tests from using it.
+pub fn with_memchain<T>(f: impl FnOnce(&mut Memchain) -> T) -> T {
+    f(&mut *MEMCHAIN.lock().unwrap_or_else(PoisonError::into_inner))
 }
 
 pub fn create_account(initial_balance: u128) -> Address {
-    MEMCHAIN.with(|memchain| {
-        let mut memchain = memchain.borrow_mut();
-
-        let new_addr = NEXT_ADDR.with(|next_addr| {
-            let mut next_addr = next_addr.borrow_mut();
-            let mut addr = Address::default();
-            let last_block = memchain.last_block();
-            loop {
-                let next_addr_bytes = next_addr.to_le_bytes();
-                (addr.0)[..next_addr_bytes.len()].copy_from_slice(&next_addr_bytes);
-                *next_addr += 1;
-                if last_block.account_meta_at(&addr).is_none() {
-                    break addr;
-                }
+    with_memchain(|memchain| {
+        let mut addr = Address::default();
+        let new_addr = loop {
+            let next_addr_bytes = NEXT_ADDR.fetch_add(1, Ordering::SeqCst).to_le_bytes();
+            (addr.0)[..next_addr_bytes.len()].copy_from_slice(&next_addr_bytes);
+            if memchain.last_block().account_meta_at(&addr).is_none() {
+                break addr;
             }
-        });
+        };
 
         // TODO(#77)
         memchain.last_block_mut().transact(
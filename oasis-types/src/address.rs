@@ -31,10 +31,51 @@ impl Address {
         std::path::PathBuf::from(hex::encode(self))
     }
 
+    /// Parses the `Address` represented by `path`, as returned by `Address::path_repr`.
+    /// Unlike `Address::from_str`, only the lowercase hex of the address is accepted, so that
+    /// every address has exactly one path.
+    pub fn from_path_repr(path: &std::path::Path) -> Option<Self> {
+        let hex_str = path.to_str()?;
+        if hex_str.len() != 2 * Self::size()
+            || !hex_str
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+        {
+            return None;
+        }
+        let mut addr = Self::default();
+        addr.0.copy_from_slice(&hex::decode(hex_str).ok()?);
+        Some(addr)
+    }
+
     /// Alias for `Address::default()`.
     pub fn zero() -> Self {
         Self::default()
     }
+
+    /// Returns the hex encoding of this address in which the case of each letter encodes
+    /// a checksum, as in EIP-55: a letter is uppercase if the corresponding nibble of the
+    /// keccak256 hash of the lowercase hex is at least 8.
+    fn checksummed_hex(&self) -> String {
+        let lower_hex = hex::encode(self.0);
+        let hash = tiny_keccak::keccak256(lower_hex.as_bytes());
+        lower_hex
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                let nibble = if i % 2 == 0 {
+                    hash[i / 2] >> 4
+                } else {
+                    hash[i / 2] & 0xf
+                };
+                if nibble >= 8 {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                }
+            })
+            .collect()
+    }
 }
 
 impl AsRef<[u8]> for Address {
@@ -43,22 +84,45 @@ impl AsRef<[u8]> for Address {
     }
 }
 
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ParseAddressError {
+    #[error("invalid hex: {0}")]
+    InvalidHex(#[from] hex::FromHexError),
+
+    #[error("expected 20 bytes but got {0}")]
+    InvalidLength(usize),
+
+    /// The address is mixed-case, but the case does not match its checksum.
+    #[error("invalid checksum")]
+    InvalidChecksum,
+}
+
+/// Parses an address from hex with an optional `0x` prefix. Mixed-case hex must match the
+/// checksummed encoding produced by `Display`. All-lowercase or all-uppercase hex has no
+/// checksum and is accepted as-is.
 impl std::str::FromStr for Address {
-    type Err = hex::FromHexError;
+    type Err = ParseAddressError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let bytes: Vec<u8> = hex::decode(s)?;
+        let hex_str = if s.starts_with("0x") { &s[2..] } else { s };
+        let bytes: Vec<u8> = hex::decode(hex_str)?;
         if bytes.len() != Address::size() {
-            return Err(hex::FromHexError::InvalidStringLength);
+            return Err(ParseAddressError::InvalidLength(bytes.len()));
         }
         let mut addr = Self::default();
         addr.0.copy_from_slice(&bytes);
+        let is_mixed_case = hex_str.chars().any(|c| c.is_ascii_lowercase())
+            && hex_str.chars().any(|c| c.is_ascii_uppercase());
+        if is_mixed_case && hex_str != addr.checksummed_hex() {
+            return Err(ParseAddressError::InvalidChecksum);
+        }
         Ok(addr)
     }
 }
 
+/// Formats the address as `0x` followed by its checksummed hex.
 impl fmt::Display for Address {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "0x{}", hex::encode(self.0))
+        write!(f, "0x{}", self.checksummed_hex())
     }
 }
 
@@ -68,6 +132,23 @@ impl fmt::LowerHex for Address {
     }
 }
 
+#[cfg(feature = "serde")]
+const _IMPL_SERDE_FOR_ADDRESS_STR: () = {
+    impl serde::Serialize for Address {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(self)
+        }
+    }
+
+    impl<'de> serde::Deserialize<'de> for Address {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let addr_str: std::borrow::Cow<'de, str> =
+                serde::Deserialize::deserialize(deserializer)?;
+            addr_str.parse().map_err(serde::de::Error::custom)
+        }
+    }
+};
+
 const _IMPL_SERDE_FOR_ADDRESS: () = {
     impl oasis_borsh::BorshSerialize for Address {
         fn serialize<W: std::io::Write>(&self, writer: &mut W) -> Result<(), std::io::Error> {
@@ -90,7 +171,7 @@ mod tests {
 
     #[test]
     fn convert_str() {
-        use std::str::FromStr;
+        use std::{path::Path, str::FromStr};
 
         let addr = Address([
             96, 255, 103, 244, 45, 95, 214, 205, 158, 83, 176, 57, 114, 69, 94, 82, 182, 223, 75,
@@ -99,13 +180,52 @@ mod tests {
         let addr_str = "60ff67f42d5fd6cd9e53b03972455e52b6df4b1c";
         assert_eq!(&addr.path_repr(), std::path::Path::new(addr_str));
         assert_eq!(&format!("{:x}", addr), addr_str);
-        assert_eq!(format!("{}", addr), format!("0x{}", addr_str));
         assert_eq!(Address::from_str(addr_str).unwrap(), addr);
-        assert!(Address::from_str(&addr_str[1..]).is_err());
-        assert!(Address::from_str(&format!("{}ab", addr_str)).is_err());
+        assert_eq!(Address::from_path_repr(&addr.path_repr()), Some(addr));
+        assert_eq!(Address::from_path_repr(Path::new(&addr_str[2..])), None);
+        assert_eq!(
+            Address::from_path_repr(Path::new(&format!("0x{}", addr_str))),
+            None
+        );
+        assert_eq!(
+            Address::from_path_repr(Path::new(&addr_str.to_uppercase())),
+            None
+        );
+        assert_eq!(
+            Address::from_str(&addr_str[1..]),
+            Err(ParseAddressError::InvalidHex(hex::FromHexError::OddLength))
+        );
+        assert_eq!(
+            Address::from_str(&format!("{}ab", addr_str)),
+            Err(ParseAddressError::InvalidLength(21))
+        );
         assert!(Address::from_str("zz").is_err());
     }
 
+    #[test]
+    fn convert_checksummed_str() {
+        use std::str::FromStr;
+
+        // Test vectors from EIP-55.
+        for addr_str in &[
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+        ] {
+            let addr = Address::from_str(addr_str).unwrap();
+            assert_eq!(&addr.to_string(), addr_str);
+            assert_eq!(Address::from_str(&addr_str[2..]), Ok(addr));
+            assert_eq!(Address::from_str(&addr_str.to_lowercase()), Ok(addr));
+            assert_eq!(Address::from_str(&addr_str[2..].to_uppercase()), Ok(addr));
+        }
+
+        assert_eq!(
+            Address::from_str("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD"),
+            Err(ParseAddressError::InvalidChecksum)
+        );
+    }
+
     #[test]
     fn convert_raw() {
         let addr = Address([
@@ -122,6 +242,18 @@ mod serde_tests {
 
     use oasis_borsh::{BorshDeserialize as _, BorshSerialize as _};
 
+    #[test]
+    fn roundtrip_serde_address() {
+        let addr_str = "\"0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed\"";
+        let addr: Address = serde_json::from_str(addr_str).unwrap();
+        assert_eq!(serde_json::to_string(&addr).unwrap(), addr_str);
+        assert!(serde_json::from_str::<Address>(&addr_str.to_lowercase()).is_ok());
+        assert!(
+            serde_json::from_str::<Address>("\"0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD\"")
+                .is_err()
+        );
+    }
+
     #[test]
     fn roundtrip_serialize_address() {
         let bytes = [1u8; 20];
@@ -35,6 +35,139 @@ impl Balance {
     pub const fn size() -> usize {
         std::mem::size_of::<Self>()
     }
+
+    /// Returns `self + other`, or `None` on overflow.
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    /// Returns `self - other`, or `None` on underflow.
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    /// Returns `self * factor`, or `None` on overflow.
+    pub fn checked_mul(self, factor: u128) -> Option<Self> {
+        self.0.checked_mul(factor).map(Self)
+    }
+
+    /// Returns `self / divisor`, or `None` if `divisor` is zero.
+    pub fn checked_div(self, divisor: u128) -> Option<Self> {
+        self.0.checked_div(divisor).map(Self)
+    }
+
+    /// Returns `self + other`, or the maximum balance on overflow.
+    pub fn saturating_add(self, other: Self) -> Self {
+        Self(self.0.saturating_add(other.0))
+    }
+
+    /// Returns `self - other`, or zero on underflow.
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+
+    /// Returns `self * factor`, or the maximum balance on overflow.
+    pub fn saturating_mul(self, factor: u128) -> Self {
+        Self(self.0.saturating_mul(factor))
+    }
+
+    /// Formats this balance of base units in `denom`, e.g. `1.25 TOKEN`.
+    /// Trailing zeros of the fractional part are omitted.
+    pub fn to_string_in(self, denom: &Denomination) -> String {
+        let (whole, frac) = match denom.unit() {
+            Some(unit) => (self.0 / unit, self.0 % unit),
+            None => (0, self.0), // Every balance is a fraction of a unit that doesn't fit.
+        };
+        let mut amount = whole.to_string();
+        if frac != 0 {
+            let frac_digits = format!("{:0width$}", frac, width = denom.decimals as usize);
+            amount.push('.');
+            amount.push_str(frac_digits.trim_end_matches('0'));
+        }
+        format!("{} {}", amount, denom.symbol)
+    }
+
+    /// Parses an amount in `denom`, such as `1.25 TOKEN` or `1.25`, into base units.
+    pub fn parse_in(s: &str, denom: &Denomination) -> Result<Self, ParseBalanceError> {
+        let s = s.trim();
+        let mut parts = s.rsplitn(2, char::is_whitespace);
+        let amount = match (parts.next(), parts.next()) {
+            (Some(symbol), Some(amount)) => {
+                if symbol != denom.symbol {
+                    return Err(ParseBalanceError::WrongSymbol(symbol.to_string()));
+                }
+                amount.trim_end()
+            }
+            _ => s,
+        };
+        let (whole, frac) = match amount.find('.') {
+            Some(pos) => (&amount[..pos], &amount[(pos + 1)..]),
+            None => (amount, ""),
+        };
+        let is_digits = |digits: &str| digits.bytes().all(|b| b.is_ascii_digit());
+        if (whole.is_empty() && frac.is_empty()) || !is_digits(whole) || !is_digits(frac) {
+            return Err(ParseBalanceError::InvalidAmount);
+        }
+        if frac.len() > denom.decimals as usize {
+            return Err(ParseBalanceError::TooManyDecimals(denom.decimals));
+        }
+        let frac_unit = 10u128
+            .checked_pow((denom.decimals as usize - frac.len()) as u32)
+            .ok_or(ParseBalanceError::Overflow)?;
+        let parse = |digits: &str| -> Result<u128, ParseBalanceError> {
+            match digits {
+                "" => Ok(0),
+                _ => digits.parse().map_err(|_| ParseBalanceError::Overflow),
+            }
+        };
+        let whole_units = match (parse(whole)?, denom.unit()) {
+            (0, _) => 0,
+            (whole, Some(unit)) => whole.checked_mul(unit).ok_or(ParseBalanceError::Overflow)?,
+            (_, None) => return Err(ParseBalanceError::Overflow),
+        };
+        parse(frac)?
+            .checked_mul(frac_unit)
+            .and_then(|frac_units| whole_units.checked_add(frac_units))
+            .map(Self)
+            .ok_or(ParseBalanceError::Overflow)
+    }
+}
+
+/// A unit in which balances are written, which is `10^decimals` base units.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Denomination {
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+impl Denomination {
+    pub fn new<S: Into<String>>(symbol: S, decimals: u8) -> Self {
+        Self {
+            symbol: symbol.into(),
+            decimals,
+        }
+    }
+
+    /// Returns the number of base units in one unit of this denomination, or `None` if it
+    /// does not fit in a `u128`.
+    fn unit(&self) -> Option<u128> {
+        10u128.checked_pow(u32::from(self.decimals))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ParseBalanceError {
+    #[error("invalid amount")]
+    InvalidAmount,
+
+    #[error("expected at most {0} decimal places")]
+    TooManyDecimals(u8),
+
+    #[error("unexpected denomination `{0}`")]
+    WrongSymbol(String),
+
+    #[error("amount is too large")]
+    Overflow,
 }
 
 macro_rules! impl_interop_with_prims {
@@ -86,6 +219,73 @@ mod tests {
         assert!(Balance::from_str(&u128::max_value().to_string()).unwrap() == u128::max_value());
     }
 
+    #[test]
+    fn test_checked() {
+        let max = Balance(u128::max_value());
+        assert_eq!(Balance(1).checked_add(Balance(2)), Some(Balance(3)));
+        assert_eq!(max.checked_add(Balance(1)), None);
+        assert_eq!(Balance(1).checked_sub(Balance(2)), None);
+        assert_eq!(max.checked_mul(2), None);
+        assert_eq!(Balance(6).checked_div(0), None);
+        assert_eq!(Balance(6).checked_div(4), Some(Balance(1)));
+        assert_eq!(max.saturating_add(Balance(1)), max);
+        assert_eq!(Balance(1).saturating_sub(Balance(2)), Balance(0));
+        assert_eq!(max.saturating_mul(2), max);
+    }
+
+    #[test]
+    fn test_denomination() {
+        let token = Denomination::new("TOKEN", 18);
+        let parse = |s: &str| Balance::parse_in(s, &token);
+
+        assert_eq!(parse("1.25 TOKEN"), Ok(Balance(1_250_000_000_000_000_000)));
+        assert_eq!(parse("1.25"), Ok(Balance(1_250_000_000_000_000_000)));
+        assert_eq!(parse(".5"), Ok(Balance(500_000_000_000_000_000)));
+        assert_eq!(parse("3."), Ok(Balance(3_000_000_000_000_000_000)));
+        assert_eq!(parse("0.000000000000000001"), Ok(Balance(1)));
+        assert_eq!(
+            parse("0.0000000000000000001"),
+            Err(ParseBalanceError::TooManyDecimals(18))
+        );
+        assert_eq!(
+            parse("1 OTHER"),
+            Err(ParseBalanceError::WrongSymbol("OTHER".to_string()))
+        );
+        for invalid in &["", ".", "-1", "1.2.3", "1e3", "one TOKEN"] {
+            assert_eq!(parse(*invalid), Err(ParseBalanceError::InvalidAmount));
+        }
+        assert_eq!(
+            parse(&format!("{}0", u128::max_value())),
+            Err(ParseBalanceError::Overflow)
+        );
+        assert_eq!(
+            parse("1000000000000000000000 TOKEN"),
+            Err(ParseBalanceError::Overflow)
+        );
+
+        assert_eq!(
+            Balance(1_250_000_000_000_000_000).to_string_in(&token),
+            "1.25 TOKEN"
+        );
+        assert_eq!(Balance(0).to_string_in(&token), "0 TOKEN");
+        assert_eq!(
+            Balance(1).to_string_in(&token),
+            "0.000000000000000001 TOKEN"
+        );
+        for balance in &[0, 1, 10, 1_234_567, u128::max_value()] {
+            let balance = Balance(*balance);
+            assert_eq!(parse(&balance.to_string_in(&token)), Ok(balance));
+        }
+
+        let base = Denomination::new("BASE", 0);
+        assert_eq!(Balance(42).to_string_in(&base), "42 BASE");
+        assert_eq!(Balance::parse_in("42 BASE", &base), Ok(Balance(42)));
+        assert_eq!(
+            Balance::parse_in("4.2 BASE", &base),
+            Err(ParseBalanceError::TooManyDecimals(0))
+        );
+    }
+
     #[test]
     fn test_cmp() {
         assert!(Balance(1) < 2);
@@ -0,0 +1,17 @@
+//! The names of the environment variables through which a service running on a WASI host
+//! receives the context of the current transaction.
+
+/// The address of the current service, in hex.
+pub const ADDRESS: &str = "ADDRESS";
+
+/// The address of the sender of the transaction, in hex.
+pub const SENDER: &str = "SENDER";
+
+/// The address of the account paying for the transaction, in hex.
+pub const PAYER: &str = "PAYER";
+
+/// The value sent with the transaction, in decimal.
+pub const VALUE: &str = "VALUE";
+
+/// The additional authenticated data of a confidential transaction, in base64.
+pub const AAD: &str = "AAD";
@@ -0,0 +1,180 @@
+use wasi_types::ErrNo;
+
+/// The registry of the outcomes of a transaction, which is shared by `RpcError`,
+/// `ExtStatusCode`, `blockchain_traits::TransactionOutcome` and the WASI `ErrNo`s by which
+/// hosts report outcomes to services, and can be converted losslessly to and from each of them.
+///
+/// The numeric codes are stable and may be stored or sent to clients, so new codes must
+/// only ever be appended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u32)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    Success = 0,
+    InsufficientFunds = 1,
+    InvalidInput = 2,
+    InvalidCallee = 3,
+    InsufficientGas = 4,
+
+    /// The callee aborted with an error of its own.
+    Execution = 5,
+
+    /// The transaction failed in a way from which the callee can't recover.
+    Fatal = 6,
+
+    InvalidOutput = 7,
+
+    /// The gateway through which a client sent the transaction encountered an error.
+    Gateway = 8,
+}
+
+impl ErrorCode {
+    const ALL: &'static [ErrorCode] = &[
+        ErrorCode::Success,
+        ErrorCode::InsufficientFunds,
+        ErrorCode::InvalidInput,
+        ErrorCode::InvalidCallee,
+        ErrorCode::InsufficientGas,
+        ErrorCode::Execution,
+        ErrorCode::Fatal,
+        ErrorCode::InvalidOutput,
+        ErrorCode::Gateway,
+    ];
+
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|c| c.code() == code)
+    }
+
+    /// Returns the outcome reported by a host as the raw WASI `errno`. @see `TryFrom<ErrNo>`.
+    pub fn from_errno(errno: u16) -> Option<Self> {
+        if errno == ErrNo::Fault as u16 {
+            return Some(ErrorCode::InvalidInput);
+        }
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|c| ErrNo::from(*c) as u16 == errno)
+    }
+
+    /// Returns the stable name of this code, e.g. `insufficient_funds`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCode::Success => "success",
+            ErrorCode::InsufficientFunds => "insufficient_funds",
+            ErrorCode::InvalidInput => "invalid_input",
+            ErrorCode::InvalidCallee => "invalid_callee",
+            ErrorCode::InsufficientGas => "insufficient_gas",
+            ErrorCode::Execution => "execution",
+            ErrorCode::Fatal => "fatal",
+            ErrorCode::InvalidOutput => "invalid_output",
+            ErrorCode::Gateway => "gateway",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl From<crate::ExtStatusCode> for ErrorCode {
+    fn from(status: crate::ExtStatusCode) -> Self {
+        use crate::ExtStatusCode;
+        match status {
+            ExtStatusCode::Success => ErrorCode::Success,
+            ExtStatusCode::InsufficientFunds => ErrorCode::InsufficientFunds,
+            ExtStatusCode::InvalidInput => ErrorCode::InvalidInput,
+            ExtStatusCode::NoAccount => ErrorCode::InvalidCallee,
+            ExtStatusCode::InsufficientGas => ErrorCode::InsufficientGas,
+            ExtStatusCode::Aborted => ErrorCode::Execution,
+            ExtStatusCode::Fatal => ErrorCode::Fatal,
+        }
+    }
+}
+
+/// The errno by which a WASI host reports an outcome to the service that made the call.
+impl From<ErrorCode> for ErrNo {
+    fn from(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::Success => ErrNo::Success,
+            ErrorCode::InsufficientFunds => ErrNo::DQuot,
+            ErrorCode::InvalidInput => ErrNo::Inval,
+            ErrorCode::InvalidCallee => ErrNo::NoEnt,
+            ErrorCode::InsufficientGas => ErrNo::TimedOut,
+            ErrorCode::Execution => ErrNo::ConnAborted,
+            ErrorCode::Fatal => ErrNo::NotRecoverable,
+            ErrorCode::InvalidOutput => ErrNo::BadMsg,
+            ErrorCode::Gateway => ErrNo::Proto,
+        }
+    }
+}
+
+/// Fails with the `ErrNo` if it doesn't report an outcome. Hosts also report `InvalidInput`
+/// as `ErrNo::Fault` when the arguments of a call can't be read from the service's memory.
+impl std::convert::TryFrom<ErrNo> for ErrorCode {
+    type Error = ErrNo;
+
+    fn try_from(errno: ErrNo) -> Result<Self, Self::Error> {
+        Self::from_errno(errno as u16).ok_or(errno)
+    }
+}
+
+/// Serialized as the numeric code.
+#[cfg(feature = "serde")]
+const _IMPL_SERDE_FOR_ERROR_CODE: () = {
+    impl serde::Serialize for ErrorCode {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_u32(self.code())
+        }
+    }
+
+    impl<'de> serde::Deserialize<'de> for ErrorCode {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let code: u32 = serde::Deserialize::deserialize(deserializer)?;
+            Self::from_code(code)
+                .ok_or_else(|| serde::de::Error::custom(format!("unknown error code: {}", code)))
+        }
+    }
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes() {
+        for (i, code) in ErrorCode::ALL.iter().enumerate() {
+            assert_eq!(code.code(), i as u32);
+            assert_eq!(ErrorCode::from_code(code.code()), Some(*code));
+        }
+        assert_eq!(ErrorCode::from_code(ErrorCode::ALL.len() as u32), None);
+    }
+
+    #[test]
+    fn test_errnos() {
+        use std::convert::TryFrom as _;
+        for code in ErrorCode::ALL.iter().copied() {
+            assert_eq!(ErrorCode::try_from(ErrNo::from(code)), Ok(code));
+            assert_eq!(ErrorCode::from_errno(ErrNo::from(code) as u16), Some(code));
+        }
+        assert_eq!(
+            ErrorCode::try_from(ErrNo::Fault),
+            Ok(ErrorCode::InvalidInput)
+        );
+        assert_eq!(ErrorCode::try_from(ErrNo::BadF), Err(ErrNo::BadF));
+    }
+
+    #[test]
+    fn test_ext_status_codes() {
+        for code in 0..ErrorCode::ALL.len() as u32 {
+            if let Some(status) = crate::ExtStatusCode::from_u32(code) {
+                assert_eq!(ErrorCode::from(status).code(), code);
+            }
+        }
+    }
+}
@@ -1,11 +1,15 @@
 #[macro_use]
 extern crate derive_more;
 
+pub mod env;
+
 mod address;
 mod balance;
+mod error_code;
 
-pub use address::Address;
-pub use balance::Balance;
+pub use address::{Address, ParseAddressError};
+pub use balance::{Balance, Denomination, ParseBalanceError};
+pub use error_code::ErrorCode;
 
 #[derive(PartialEq, Eq, Debug)]
 #[repr(u32)]
@@ -16,6 +20,9 @@ pub enum ExtStatusCode {
     InsufficientFunds,
     InvalidInput,
     NoAccount,
+    InsufficientGas,
+    Aborted,
+    Fatal,
 }
 
 impl ExtStatusCode {
@@ -25,15 +32,42 @@ impl ExtStatusCode {
             1 => ExtStatusCode::InsufficientFunds,
             2 => ExtStatusCode::InvalidInput,
             3 => ExtStatusCode::NoAccount,
+            4 => ExtStatusCode::InsufficientGas,
+            5 => ExtStatusCode::Aborted,
+            6 => ExtStatusCode::Fatal,
             _ => return None,
         })
     }
 }
 
+/// Fails with the `ErrorCode` if it can't be returned by the host.
+impl std::convert::TryFrom<ErrorCode> for ExtStatusCode {
+    type Error = ErrorCode;
+
+    fn try_from(code: ErrorCode) -> Result<Self, Self::Error> {
+        Ok(match code {
+            ErrorCode::Success => ExtStatusCode::Success,
+            ErrorCode::InsufficientFunds => ExtStatusCode::InsufficientFunds,
+            ErrorCode::InvalidInput => ExtStatusCode::InvalidInput,
+            ErrorCode::InvalidCallee => ExtStatusCode::NoAccount,
+            ErrorCode::InsufficientGas => ExtStatusCode::InsufficientGas,
+            ErrorCode::Execution => ExtStatusCode::Aborted,
+            ErrorCode::Fatal => ExtStatusCode::Fatal,
+            ErrorCode::InvalidOutput | ErrorCode::Gateway => return Err(code),
+        })
+    }
+}
+
 #[derive(Clone, Default, Debug, PartialEq, Eq)]
 pub struct AccountMeta {
     pub balance: u128,
     pub expiry: Option<std::time::Duration>,
+
+    /// The number of bytes occupied by the account's storage.
+    pub storage_size: u64,
+
+    /// The number of transactions sent by the account.
+    pub nonce: u64,
 }
 
 #[derive(Clone, Default, Debug, PartialEq, Eq)]
@@ -68,6 +102,10 @@ pub enum RpcError {
     #[error("an application error occurred")]
     Execution(Vec<u8>),
 
+    /// The transaction failed in a way from which the application can't recover.
+    #[error("a fatal error occurred")]
+    Fatal,
+
     /// The gateway client encountered an error.
     #[cfg(not(target_os = "wasi"))]
     #[error("gateway error: {0}")]
@@ -113,6 +151,10 @@ const _IMPL_SERDE_FOR_RPC_ERROR: () = {
                     writer.write_all(&variant_idx.to_le_bytes())?;
                     oasis_borsh::BorshSerialize::serialize(&e.to_string(), writer)?;
                 }
+                RpcError::Fatal => {
+                    let variant_idx = 7u8;
+                    writer.write_all(&variant_idx.to_le_bytes())?;
+                }
             }
             Ok(())
         }
@@ -137,6 +179,7 @@ const _IMPL_SERDE_FOR_RPC_ERROR: () = {
                     let err_str: String = oasis_borsh::BorshDeserialize::deserialize(reader)?;
                     RpcError::Gateway(anyhow::anyhow!(err_str))
                 }
+                7u8 => RpcError::Fatal,
                 _ => {
                     return Err(std::io::Error::new(
                         std::io::ErrorKind::InvalidInput,
@@ -156,4 +199,109 @@ impl RpcError {
             _ => None,
         }
     }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            RpcError::InvalidCallee => ErrorCode::InvalidCallee,
+            RpcError::InsufficientFunds => ErrorCode::InsufficientFunds,
+            RpcError::InsufficientGas => ErrorCode::InsufficientGas,
+            RpcError::InvalidInput => ErrorCode::InvalidInput,
+            RpcError::InvalidOutput(_) => ErrorCode::InvalidOutput,
+            RpcError::Execution(_) => ErrorCode::Execution,
+            RpcError::Fatal => ErrorCode::Fatal,
+            #[cfg(not(target_os = "wasi"))]
+            RpcError::Gateway(_) => ErrorCode::Gateway,
+        }
+    }
+
+    /// Returns the output carried by this error, if any.
+    pub fn output(&self) -> Option<&[u8]> {
+        match self {
+            RpcError::InvalidOutput(output) | RpcError::Execution(output) => Some(&output),
+            _ => None,
+        }
+    }
+
+    /// Returns the error that has `code` and, if the error carries output, `output`.
+    /// The output of a `Gateway` error is its message.
+    /// Returns `None` for `ErrorCode::Success` and for codes that can't occur on this target.
+    pub fn from_code(code: ErrorCode, output: Vec<u8>) -> Option<Self> {
+        Some(match code {
+            ErrorCode::Success => return None,
+            ErrorCode::InsufficientFunds => RpcError::InsufficientFunds,
+            ErrorCode::InvalidInput => RpcError::InvalidInput,
+            ErrorCode::InvalidCallee => RpcError::InvalidCallee,
+            ErrorCode::InsufficientGas => RpcError::InsufficientGas,
+            ErrorCode::Execution => RpcError::Execution(output),
+            ErrorCode::Fatal => RpcError::Fatal,
+            ErrorCode::InvalidOutput => RpcError::InvalidOutput(output),
+            #[cfg(not(target_os = "wasi"))]
+            ErrorCode::Gateway => RpcError::Gateway(anyhow::anyhow!(String::from_utf8_lossy(
+                &output
+            )
+            .into_owned())),
+            #[cfg(target_os = "wasi")]
+            ErrorCode::Gateway => return None,
+        })
+    }
+}
+
+/// Serialized for clients as `{ "code": <ErrorCode>, "name": <ErrorCode::name>, "message":
+/// <Display>, "output": <hex, if any> }`.
+#[cfg(feature = "serde")]
+const _IMPL_SERDE_FOR_RPC_ERROR_JSON: () = {
+    use serde::ser::SerializeStruct as _;
+
+    impl serde::Serialize for RpcError {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let output = self.output();
+            let mut error =
+                serializer.serialize_struct("RpcError", 3 + output.is_some() as usize)?;
+            error.serialize_field("code", &self.code())?;
+            error.serialize_field("name", self.code().name())?;
+            error.serialize_field("message", &self.to_string())?;
+            if let Some(output) = output {
+                error.serialize_field("output", &hex::encode(output))?;
+            }
+            error.end()
+        }
+    }
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rpc_error_codes() {
+        let errors = vec![
+            RpcError::InvalidCallee,
+            RpcError::InsufficientFunds,
+            RpcError::InsufficientGas,
+            RpcError::InvalidInput,
+            RpcError::InvalidOutput(b"output".to_vec()),
+            RpcError::Execution(b"output".to_vec()),
+            RpcError::Fatal,
+        ];
+        for err in errors {
+            let output = err.output().unwrap_or_default().to_vec();
+            let converted = RpcError::from_code(err.code(), output).unwrap();
+            assert_eq!(converted.code(), err.code());
+            assert_eq!(converted.output(), err.output());
+        }
+        assert!(RpcError::from_code(ErrorCode::Success, Vec::new()).is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn rpc_error_json() {
+        assert_eq!(
+            serde_json::to_string(&RpcError::Execution(b"oops".to_vec())).unwrap(),
+            r#"{"code":5,"name":"execution","message":"an application error occurred","output":"6f6f7073"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&RpcError::InsufficientGas).unwrap(),
+            r#"{"code":4,"name":"insufficient_gas","message":"not enough gas provided to transaction"}"#
+        );
+    }
 }
@@ -19,6 +19,16 @@ impl ServiceA {
         b.return_ref_struct(&Context::default(), "value").unwrap();
         b.random(&Context::default(), b::Number(42))
     }
+
+    pub fn echo_wide(
+        &self,
+        _ctx: &Context,
+        amount: u128,
+        delta: i128,
+        count: usize,
+    ) -> (u128, i128, usize) {
+        (amount, delta, count)
+    }
 }
 
 fn main() {
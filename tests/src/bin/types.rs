@@ -78,6 +78,10 @@ impl TestService {
         unimplemented!()
     }
 
+    pub fn wide(&self, ctx: &Context, a1: u128, a2: i128, a3: usize) -> isize {
+        unimplemented!()
+    }
+
     #[oasis_std::default]
     pub fn the_default_fn(&mut self, ctx: &Context) -> std::result::Result<Option<u64>, Set<u32>> {
         unimplemented!()
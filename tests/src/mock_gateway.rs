@@ -38,6 +38,7 @@ impl MockGateway {
                 InvalidInput => InvalidInput,
                 InvalidOutput(output) => InvalidOutput(output.clone()),
                 Execution(err) => Execution(err.clone()),
+                Fatal => Fatal,
                 Gateway(_) => unreachable!(),
             }
         })
@@ -0,0 +1,178 @@
+use oasis_std::{abi::*, exe::RawEvent, Address, Event};
+
+#[derive(Serialize, Deserialize, Event, Debug, PartialEq)]
+pub struct Transfer {
+    #[indexed]
+    from: Address,
+    #[indexed]
+    to: Address,
+    amount: u64,
+}
+
+#[derive(Serialize, Deserialize, Event, Debug, PartialEq)]
+pub struct Approval(#[indexed] Address, #[indexed] Address, u64);
+
+fn raw_event<E: Event>(event: &E) -> RawEvent {
+    RawEvent {
+        emitter: Address::default(),
+        topics: event.topics(),
+        data: event.try_to_vec().unwrap(),
+    }
+}
+
+#[test]
+fn test_try_decode() {
+    let transfer = Transfer {
+        from: Address([1u8; 20]),
+        to: Address([2u8; 20]),
+        amount: 42,
+    };
+    let raw_transfer = raw_event(&transfer);
+    assert_eq!(Transfer::try_decode(&raw_transfer), Some(transfer));
+    assert_eq!(Approval::try_decode(&raw_transfer), None);
+
+    let mut bad_topics = raw_transfer.clone();
+    bad_topics.topics.swap(1, 2);
+    assert_eq!(Transfer::try_decode(&bad_topics), None);
+
+    let mut bad_data = raw_transfer;
+    bad_data.data.pop();
+    assert_eq!(Transfer::try_decode(&bad_data), None);
+}
+
+#[test]
+fn test_topic_filter() {
+    let alice = Address([1u8; 20]);
+    let bob = Address([2u8; 20]);
+    let raw_transfer = raw_event(&Transfer {
+        from: alice,
+        to: bob,
+        amount: 42,
+    });
+    let raw_approval = raw_event(&Approval(alice, bob, 42));
+
+    assert!(Transfer::topic_filter().matches(&raw_transfer));
+    assert!(!Transfer::topic_filter().matches(&raw_approval));
+    assert!(Transfer::topic_filter()
+        .with_from(&alice)
+        .matches(&raw_transfer));
+    assert!(Transfer::topic_filter()
+        .with_to(&bob)
+        .matches(&raw_transfer));
+    assert!(!Transfer::topic_filter()
+        .with_from(&bob)
+        .with_to(&bob)
+        .matches(&raw_transfer));
+    assert!(Approval::topic_filter()
+        .with_0(&alice)
+        .with_1(&bob)
+        .matches(&raw_approval));
+    assert!(Approval::topic_filter()
+        .with_1(&bob)
+        .into_inner()
+        .matches(&raw_approval));
+}
+
+#[derive(Serialize, Deserialize, Event, Debug, PartialEq)]
+pub enum Token {
+    Transfer {
+        #[indexed]
+        from: Address,
+        #[indexed]
+        to: Address,
+        amount: u64,
+    },
+    Burn(#[indexed] Address, u64),
+    Paused,
+}
+
+#[test]
+fn test_signature_topic() {
+    let transfer = Transfer {
+        from: Address([1u8; 20]),
+        to: Address([2u8; 20]),
+        amount: 42,
+    };
+    assert_eq!(
+        transfer.topics()[0],
+        oasis_std::crypto::keccak256(b"Transfer(Address,Address,u64)")
+    );
+
+    let token_transfer = Token::Transfer {
+        from: Address([1u8; 20]),
+        to: Address([2u8; 20]),
+        amount: 42,
+    };
+    assert_eq!(
+        token_transfer.topics()[0],
+        oasis_std::crypto::keccak256(b"Token::Transfer(Address,Address,u64)")
+    );
+    assert_eq!(transfer.topics()[1..], token_transfer.topics()[1..]);
+    assert_eq!(Transfer::try_decode(&raw_event(&token_transfer)), None);
+}
+
+#[test]
+fn test_enum_event() {
+    let alice = Address([1u8; 20]);
+    let events = vec![
+        Token::Transfer {
+            from: alice,
+            to: Address([2u8; 20]),
+            amount: 42,
+        },
+        Token::Burn(alice, 7),
+        Token::Paused,
+    ];
+    for event in events.iter() {
+        assert_eq!(Token::try_decode(&raw_event(event)).as_ref(), Some(event));
+    }
+
+    let raw_transfer = raw_event(&events[0]);
+    let raw_burn = raw_event(&events[1]);
+    let raw_paused = raw_event(&events[2]);
+    assert!(Token::transfer_topic_filter()
+        .with_from(&alice)
+        .matches(&raw_transfer));
+    assert!(!Token::transfer_topic_filter().matches(&raw_burn));
+    assert!(Token::burn_topic_filter().with_0(&alice).matches(&raw_burn));
+    assert!(Token::paused_topic_filter().matches(&raw_paused));
+    assert!(!Token::paused_topic_filter().matches(&raw_burn));
+}
+
+type Minter = Address;
+
+#[derive(Serialize, Deserialize, Event, Debug, PartialEq)]
+pub struct Mint {
+    #[indexed]
+    to: oasis_std::Address,
+    minter: Minter,
+    amount: std::option::Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Event)]
+pub struct Deposit<T: Serialize + Deserialize> {
+    amount: T,
+}
+
+#[test]
+fn test_signature_types() {
+    let mint = Mint {
+        to: Address([1u8; 20]),
+        minter: Address([2u8; 20]),
+        amount: Some(42),
+    };
+    assert_eq!(
+        mint.topics()[0],
+        oasis_std::crypto::keccak256(b"Mint(Address,Minter,Option<u64>)")
+    );
+
+    let deposit_topic = Deposit { amount: 42u64 }.topics()[0];
+    assert_eq!(deposit_topic, oasis_std::crypto::keccak256(b"Deposit(T)"));
+    assert_eq!(
+        deposit_topic,
+        Deposit {
+            amount: "42".to_string()
+        }
+        .topics()[0]
+    );
+}
@@ -1,3 +1,4 @@
+mod events;
 mod idl_gen;
 mod xcc;
 
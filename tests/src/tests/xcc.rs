@@ -1,4 +1,4 @@
-use oasis_std::{abi_encode, Address, Context};
+use oasis_std::{abi::Deserialize as _, abi_encode, Address, Context, RpcError};
 
 use crate::mock_gateway::{DeployCall, GatewayHandlers, MockGateway, RpcCall};
 
@@ -54,3 +54,26 @@ fn test_native_client() {
         assert_eq!(payload, &expected_rpc_payload);
     }
 }
+
+#[test]
+fn test_wide_ints() {
+    let gateway = MockGateway::new(GatewayHandlers {
+        deploy: box move |_| Ok(Address([1u8; 20])),
+        rpc: box move |_, payload| {
+            // A `usize` is sent as a `u64`.
+            let (func_idx, amount, delta, count) = <(u8, u128, i128, u64)>::try_from_slice(payload)
+                .map_err(|_| RpcError::InvalidInput)?;
+            assert_eq!(func_idx, 1);
+            Ok(abi_encode!((amount, delta, count)).unwrap())
+        },
+    });
+
+    let client = a::ServiceAClient::deploy(&gateway, &Context::default(), "message").unwrap();
+
+    let args = (u128::max_value(), i128::min_value(), u64::max_value());
+    let output = client
+        .echo_wide(&Context::default(), args.0, args.1, args.2)
+        .unwrap();
+    assert_eq!(output, args);
+    assert_eq!(gateway.rpcs.borrow()[0].payload.len(), 1 + 16 + 16 + 8);
+}